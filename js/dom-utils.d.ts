@@ -38,6 +38,15 @@ export type ExtractEvent<T, P extends string> = {
 
 export type SpecialProps = {
   "$if"?: boolean,
+  "$elseif"?: boolean,
+  "$else"?: boolean,
+  "$switch"?: unknown,
+  "$case"?: unknown,
+  "$default"?: boolean,
+  "$show"?: boolean,
+  "$key"?: unknown,
+  "$component"?: boolean,
+  "$preserveWhitespace"?: boolean,
 };
 
 export type EventHandlers<T> = ExtractEvent<T, "on:"> & ExtractEvent<T, "g:on">;
@@ -54,6 +63,16 @@ export type StyleProps = {
   [K in `var:${string}`]?: Option<string>;
 } & {
   [K in `class:${string}`]?: Option<BoolAttr>;
+} & {
+  [K in `prop:${string}`]?: unknown;
+} & {
+  [K in `attr:${string}`]?: Option<BoolAttr | string | number>;
+} & {
+  [K in `bind:${"value" | "checked" | "group" | "this"}`]?: unknown;
+} & {
+  [K in `use:${string}`]?: unknown;
+} & {
+  [K in `let:${string}`]?: unknown;
 } & {
   $transition?: Option<BoolAttr>;
 } & {
@@ -439,6 +439,8 @@ interface ProgressHTMLAttributes<T> extends HTMLAttributes<T> {
 
 interface SlotHTMLAttributes<T> extends HTMLAttributes<T> {
   name?: Option<string>;
+  /** Every other prop is passed to the slot function, callers read it with `let:` */
+  [prop: string]: unknown;
 }
 
 interface ScriptHTMLAttributes<T> extends HTMLAttributes<T> {
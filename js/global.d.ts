@@ -4,9 +4,14 @@ declare namespace JSX {
   type IntrinsicElements = import("./dom.d.ts").HTMLElementAttributeMap;
 
   type Tag = keyof HTMLElementTagNameMap;
-  type Slots = Record<string, () => JSX.Element> & { default: () => JSX.Element[] };
+  type Slots = Record<string, (props?: Record<string, unknown>) => JSX.Element> & {
+    default: (props?: Record<string, unknown>) => JSX.Element[];
+  };
 
   type Component = (props: Record<string, unknown> | null, slots: Slots) => Node;
 }
 
 declare type ValueOf<T> = T[keyof T];
+
+/** Reads a JSX value once instead of tracking it, the call is removed by the compiler. */
+declare function $static<T>(value: T): T;
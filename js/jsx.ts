@@ -1,10 +1,13 @@
-import { cleanup, type Running, watch, watchFn } from "~/signals";
+import { cleanup, watch, watchFn } from "~/signals";
 import type { EventName } from "./dom-utils";
 import { swapRemove, iterChildrenDeep, iterChildNodesDeep } from "./utils";
 
 export * from "~/signals";
 
-export function template(html: string): () => Node {
+/**
+ * @param root Wrapper tag for detached SVG or MathML elements so they're parsed in the right namespace.
+ */
+export function template(html: string, root?: "svg" | "math"): () => Node {
   const needsXml = /<select|<ul|<table/i.test(html) && /<slot/i.test(html);
 
   function xmlToHtml(node: Element) {
@@ -43,6 +46,10 @@ export function template(html: string): () => Node {
       }
     }
     const templ = document.createElement("template");
+    if (root) {
+      templ.innerHTML = `<${root}>${html}</${root}>`;
+      return templ.content.firstChild!.firstChild as Node;
+    }
     templ.innerHTML = html;
     return templ.content.firstChild as Node;
   }
@@ -82,7 +89,37 @@ export function createGlobalEvent(evName: EventName) {
   return listeners;
 }
 
-export function destroyNode(node: Element) {
+const delegatedEvents = new Set<EventName>();
+
+/**
+ * Registers a single document listener that walks up from the target calling the handlers
+ * stored in each node's `$$<evName>` property, `currentTarget` points to the node being handled.
+ */
+export function delegateEvent(evName: EventName) {
+  if (delegatedEvents.has(evName)) {
+    return;
+  }
+  delegatedEvents.add(evName);
+
+  const key = `$$${evName}`;
+  document.addEventListener(evName, (e) => {
+    let node = e.target as Node | null;
+    Object.defineProperty(e, "currentTarget", { configurable: true, get: () => node });
+
+    while (node) {
+      const handler = (node as unknown as Attributes)[key] as EventHandler | undefined;
+      if (handler) {
+        handler.call(node, e);
+        if (e.cancelBubble) {
+          return;
+        }
+      }
+      node = node.parentNode;
+    }
+  });
+}
+
+export function destroyNode(node: Node) {
   iterChildNodesDeep(node, (t) => t.dispatchEvent(new CustomEvent("destroy")));
 }
 
@@ -216,9 +253,143 @@ export function conditionalRender(
   return condition() ? node! || (node = create()) : anchor;
 }
 
+/**
+ * Nodes returned by a render function, the children of a fragment are taken out of it since
+ * inserting the fragment empties it.
+ */
+function renderedNodes(node: Node | Node[]): ChildNode[] {
+  if (node instanceof Array) {
+    return node.flatMap(renderedNodes);
+  }
+  return node instanceof DocumentFragment ? [...node.childNodes] : [node as ChildNode];
+}
+
+/**
+ * Renders the branch picked by `select` before `anchor`, -1 renders nothing.
+ * Used by `$if`/`$elseif`/`$else` chains and `$switch`, branches are created lazily and kept around.
+ * The anchor stays in place so a branch rendering no nodes still has a position.
+ */
+export function branchRender(
+  anchor: Comment,
+  select: () => number,
+  branches: (() => Element | Element[])[],
+) {
+  const nodes: (ChildNode[] | undefined)[] = [];
+  let current = -1;
+  let destroyed = false;
+
+  const destroy = () => {
+    if (destroyed) {
+      return;
+    }
+    destroyed = true;
+    cleanup(running);
+    nodes.forEach((n, i) => {
+      if (n && i !== current) {
+        n.forEach(destroyNode);
+      }
+    });
+  };
+
+  anchor.addEventListener("destroy", destroy);
+
+  const create = (i: number) => {
+    const created = renderedNodes(branches[i]());
+    created.forEach((n) => {
+      n.addEventListener("destroy", () => {
+        if (nodes[current] === created) {
+          destroy();
+          anchor.remove();
+        }
+      });
+    });
+    return created;
+  };
+
+  const show = (i: number) => {
+    if (i === current) {
+      return;
+    }
+    const prev = current === -1 ? [] : nodes[current]!;
+    const next = i === -1 ? [] : nodes[i] || (nodes[i] = create(i));
+    anchor.before(...next);
+    prev.forEach((n) => n.remove());
+    current = i;
+  };
+
+  const running = watchFn(select, () => show(select()));
+
+  return [...(current === -1 ? [] : nodes[current]!), anchor];
+}
+
+/**
+ * Recreates the nodes before `anchor` whenever `key` changes, the previous ones are destroyed.
+ */
+export function keyedRender(
+  anchor: Comment,
+  createNode: () => Element | Element[],
+  key: () => unknown,
+) {
+  let nodes: ChildNode[] | undefined;
+  let prevKey: unknown;
+
+  anchor.addEventListener("destroy", () => {
+    if (nodes?.length) {
+      nodes.forEach(destroyNode);
+    } else {
+      cleanup(running);
+    }
+  });
+
+  const create = () => {
+    const created = renderedNodes(createNode());
+    created.forEach((n) => {
+      n.addEventListener("destroy", () => {
+        if (nodes === created) {
+          cleanup(running);
+          anchor.remove();
+        }
+      });
+    });
+    return created;
+  };
+
+  const running = watchFn(key, () => {
+    const k = key();
+    if (nodes && Object.is(k, prevKey)) {
+      return;
+    }
+    prevKey = k;
+
+    const prev = nodes;
+    nodes = create();
+    anchor.before(...nodes);
+    if (prev) {
+      prev.forEach(destroyNode);
+    }
+  });
+
+  return [...nodes!, anchor];
+}
+
+const attrNamespaces: Record<string, string> = {
+  xlink: "http://www.w3.org/1999/xlink",
+  xml: "http://www.w3.org/XML/1998/namespace",
+  xmlns: "http://www.w3.org/2000/xmlns/",
+};
+
 export function setAttribute(node: Element, attr: string, value: unknown) {
+  const i = attr.indexOf(":");
+  const ns = i === -1 ? undefined : attrNamespaces[attr.slice(0, i)];
+
   if (value == null || value === false) {
-    node.removeAttribute(attr);
+    if (ns) {
+      node.removeAttributeNS(ns, attr.slice(i + 1));
+    } else {
+      node.removeAttribute(attr);
+    }
+  } else if (ns) {
+    node.setAttributeNS(ns, attr, value as string);
   } else {
     node.setAttribute(attr, value as string);
   }
@@ -228,22 +399,270 @@ export function trackAttribute(
   node: Element,
   attr: string,
   value: () => unknown,
+  asProperty = attr === "value" || attr === "checked",
+) {
+  if (asProperty) {
+    trackProperty(node, attr, value);
+    return;
+  }
+
+  const running = watch(() => {
+    setAttribute(node, attr, value());
+  });
+
+  node.addEventListener("destroy", () => cleanup(running));
+}
+
+export function trackProperty(
+  node: Element,
+  key: string,
+  value: () => unknown,
 ) {
-  let running: Running<unknown>;
+  const running = watch(() => {
+    (node as unknown as Attributes)[key] = value();
+  });
+
+  node.addEventListener("destroy", () => cleanup(running));
+}
 
-  if (attr === "value" || attr === "checked") {
-    running = watch(() => {
-      node[attr] = value();
+type Attributes = Record<string, unknown>;
+
+export type ActionReturn<P> = { update?: (params: P) => void; destroy?: () => void } | void;
+
+export type Action<P = undefined> = (node: Element, params: () => P) => ActionReturn<P>;
+
+/**
+ * Runs a `use:` action, `update` is called whenever the params change and `destroy` once the node is destroyed or unmounted.
+ * An unmounted node that is mounted again, like a moved keyed item, runs the action again.
+ */
+export function useAction<P>(node: Element, action: Action<P>, params: () => P = () => undefined as P) {
+  let destroyed = false;
+  let stop: (() => void) | undefined;
+
+  const start = () => {
+    const ret = action(node, params);
+    let first = true;
+    const running = ret?.update && watchFn(params, () => {
+      if (first) {
+        first = false;
+      } else {
+        ret?.update?.(params());
+      }
     });
+
+    stop = () => {
+      stop = undefined;
+      if (running) {
+        cleanup(running);
+      }
+      ret?.destroy?.();
+    };
+  };
+  start();
+
+  node.addEventListener("destroy", () => {
+    destroyed = true;
+    stop?.();
+  });
+  node.addEventListener("unmount", () => stop?.());
+  node.addEventListener("mount", () => {
+    if (!destroyed && !stop) {
+      start();
+    }
+  });
+}
+
+/** Getter/setter pair written by `bind:` directives, a `ref()` tuple works as is. */
+type Binding<T> = readonly [get: () => T, set: (v: T) => void];
+
+export function bindProperty(
+  node: HTMLInputElement,
+  key: "value" | "checked",
+  evName: EventName,
+  [get, set]: Binding<unknown>,
+) {
+  trackProperty(node, key, get);
+  node.addEventListener(evName, () => {
+    if (key === "value" && (node.type === "number" || node.type === "range")) {
+      set(node.valueAsNumber);
+    } else {
+      set(node[key]);
+    }
+  });
+}
+
+/**
+ * Radio buttons bind to the value of the checked one, checkboxes to an array with the values of every checked box.
+ */
+export function bindGroup(node: HTMLInputElement, [get, set]: Binding<unknown>) {
+  const running = watch(() => {
+    const v = get();
+    node.checked = v instanceof Array ? v.includes(node.value) : v === node.value;
+  });
+
+  node.addEventListener("change", () => {
+    if (node.type === "radio") {
+      set(node.value);
+      return;
+    }
+    const v = get();
+    const values = v instanceof Array ? v.filter((x) => x !== node.value) : [];
+    set(node.checked ? [...values, node.value] : values);
+  });
+  node.addEventListener("destroy", () => cleanup(running));
+}
+
+function applyAttribute(node: HTMLElement, key: string, value: unknown, prev: unknown) {
+  if (key.startsWith("on:")) {
+    if (value === prev) {
+      return;
+    }
+    const evName = key.slice(3) as EventName;
+    if (prev instanceof Array) {
+      node.removeEventListener(evName, prev[0], prev[1]);
+    } else if (prev) {
+      node.removeEventListener(evName, prev as EventHandler);
+    }
+    if (value) {
+      addLocalEvent(node, evName, value as EventHandler);
+    }
+  } else if (key.startsWith("class:")) {
+    node.classList.toggle(key.slice(6), !!value);
+  } else if (key.startsWith("style:") || key.startsWith("var:")) {
+    const rule = key.startsWith("var:") ? `--${key.slice(4)}` : key.slice(6);
+    if (value == null || value === false) {
+      node.style.removeProperty(rule);
+    } else {
+      node.style.setProperty(rule, `${value}`);
+    }
+  } else if (key.startsWith("prop:")) {
+    (node as unknown as Attributes)[key.slice(5)] = value;
+  } else if (key.startsWith("attr:")) {
+    setAttribute(node, key.slice(5), value);
+  } else if (key === "$ref" || key === "$refFn" || key === "slot") {
+    return;
   } else {
-    running = watch(() => {
-      setAttribute(node, attr, value());
-    });
+    const attr = key.startsWith("$") ? key.slice(1) : key;
+    if (attr === "value" || attr === "checked") {
+      node[attr] = value;
+    } else {
+      setAttribute(node, attr, value);
+    }
   }
+}
+
+export function spreadAttributes(
+  node: HTMLElement,
+  value: Attributes | (() => Attributes),
+  overridden: string[] = [],
+) {
+  if (typeof value !== "function") {
+    for (const key in value) {
+      applyAttribute(node, key, value[key], undefined);
+    }
+    return;
+  }
+
+  let prev: Attributes = {};
+  const running = watch(() => {
+    const next: Attributes = {};
+    const attrs = value() || {};
+    for (const key in attrs) {
+      if (!overridden.includes(key)) {
+        next[key] = attrs[key];
+      }
+    }
+
+    for (const key in prev) {
+      if (!(key in next)) {
+        applyAttribute(node, key, undefined, prev[key]);
+      }
+    }
+    for (const key in next) {
+      applyAttribute(node, key, next[key], prev[key]);
+    }
+    prev = next;
+  });
 
   node.addEventListener("destroy", () => cleanup(running));
 }
 
+type PropsSource = Attributes | (() => Attributes);
+
+/**
+ * Merges component props with `{...spread}` objects without reading them eagerly,
+ * so getters on either side stay reactive. Later sources win.
+ */
+export function mergeProps(...sources: PropsSource[]): Attributes {
+  const own: Attributes = {};
+  sources.push(own);
+
+  const resolve = (s: PropsSource) => (typeof s === "function" ? s() : s) || {};
+  const find = (key: PropertyKey) => {
+    for (let i = sources.length - 1; i >= 0; i--) {
+      const s = resolve(sources[i]);
+      if (key in s) {
+        return s;
+      }
+    }
+  };
+
+  return new Proxy(own, {
+    get: (_, key) => find(key)?.[key as string],
+    set: (_, key, value) => {
+      (find(key) || own)[key as string] = value;
+      return true;
+    },
+    has: (_, key) => !!find(key),
+    ownKeys: () => {
+      const keys = new Set<string>();
+      sources.forEach((s) => Object.keys(resolve(s)).forEach((k) => keys.add(k)));
+      return [...keys];
+    },
+    getOwnPropertyDescriptor: (_, key) => {
+      const s = find(key);
+      return s && { configurable: true, enumerable: true, writable: true, value: s[key as string] };
+    },
+  });
+}
+
+/**
+ * Props left over by a `...rest` element in a destructured component, reads go
+ * through to the props object so getters stay reactive.
+ */
+export function omitProps(props: Attributes, omit: PropertyKey[]): Attributes {
+  const has = (key: PropertyKey) => !omit.includes(key) && key in props;
+
+  return new Proxy(props, {
+    get: (_, key) => (has(key) ? props[key as string] : undefined),
+    set: (_, key, value) => {
+      props[key as string] = value;
+      return true;
+    },
+    has: (_, key) => has(key),
+    ownKeys: () => Reflect.ownKeys(props).filter(has),
+    getOwnPropertyDescriptor: (_, key) =>
+      has(key) ? { configurable: true, enumerable: true, writable: true, value: props[key as string] } : undefined,
+  });
+}
+
+/**
+ * Default value of a destructured prop, `fn` runs on the first read only so
+ * each call of the component evaluates it once.
+ */
+export function lazyDefault<T>(fn: () => T): () => T {
+  let done = false;
+  let value: T;
+
+  return () => {
+    if (!done) {
+      value = fn();
+      done = true;
+    }
+    return value;
+  };
+}
+
 export function trackClass(
   target: Element,
   className: string,
@@ -319,6 +738,99 @@ export function insertChild(
   }
 }
 
+function nextFrame() {
+  return new Promise((res) => {
+    requestAnimationFrame(() => requestAnimationFrame(res));
+  });
+}
+
+/**
+ * Longest `transition-duration` plus `transition-delay` of a node in milliseconds, 0 when nothing transitions.
+ */
+function transitionTime(node: Element) {
+  const style = getComputedStyle(node);
+  const times = (value: string) =>
+    value.split(",").map((t) => parseFloat(t) * (t.trim().endsWith("ms") ? 1 : 1000) || 0);
+  const delays = times(style.transitionDelay);
+  return Math.max(0, ...times(style.transitionDuration).map((d, i) => d + delays[i % delays.length]));
+}
+
+/**
+ * Toggles `display: none` on a node that stays mounted, running the `name` transition classes when given.
+ * The node is hidden once its transition ends or is cancelled, or right away when it has no transition.
+ */
+export function trackShow(node: HTMLElement, cond: (() => boolean) | boolean, name?: string) {
+  const shown = () => (typeof cond === "function" ? cond() : cond);
+  const display = node.style.display === "none" ? "" : node.style.display;
+  const classes = ["enter-from", "enter-active", "enter-to", "leave-from", "leave-active", "leave-to"].map(
+    (c) => `${name}-${c}`,
+  );
+
+  // Bumped on every toggle so a late timer doesn't end the phase that replaced its own
+  let phase = 0;
+  const finish = () => {
+    node.classList.remove(...classes);
+    if (!shown()) {
+      node.style.display = "none";
+    }
+  };
+
+  if (name) {
+    const onEnd = (e: Event) => {
+      if (e.target === node) {
+        finish();
+      }
+    };
+    node.addEventListener("transitionend", onEnd);
+    node.addEventListener("transitioncancel", onEnd);
+  }
+
+  let firstRun = true;
+  const running = watchFn(shown, async () => {
+    const show = shown();
+    if (!name || firstRun) {
+      firstRun = false;
+      node.style.display = show ? display : "none";
+      return;
+    }
+
+    const current = ++phase;
+    const step = show ? "enter" : "leave";
+    node.classList.remove(...classes);
+    if (show) {
+      node.style.display = display;
+    }
+    node.classList.add(`${name}-${step}-from`, `${name}-${step}-active`);
+    await nextFrame();
+    if (current !== phase) {
+      return;
+    }
+    node.classList.remove(`${name}-${step}-from`);
+    node.classList.add(`${name}-${step}-to`);
+
+    // `transitionend` never fires when no property transitions, the timer leaves it a frame to arrive first
+    const time = transitionTime(node);
+    if (time) {
+      setTimeout(() => current === phase && finish(), time + 20);
+    } else {
+      finish();
+    }
+  });
+
+  node.addEventListener("destroy", () => cleanup(running));
+}
+
+/**
+ * Node returned by a component with `$show` or `$transition`, those need a single element to
+ * toggle and animate, not an array, text or fragment.
+ */
+export function singleElement(node: unknown, component: string): HTMLElement {
+  if (!(node instanceof HTMLElement)) {
+    throw new Error(`"$show" and "$transition" need ${component} to return a single element`);
+  }
+  return node;
+}
+
 export function createTransition(
   anchor: Comment,
   createNode: () => Element,
@@ -342,12 +854,6 @@ export function createTransition(
   const leaveFrom = () => `${name}-leave-from`;
   const leaveTo = () => `${name}-leave-to`;
 
-  function nextFrame() {
-    return new Promise((res) => {
-      requestAnimationFrame(() => requestAnimationFrame(res));
-    });
-  }
-
   const create = () => {
     t = createNode();
 
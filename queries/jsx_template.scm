@@ -2,16 +2,19 @@
   (jsx_element
     open_tag: (jsx_opening_element
       name: (_) @tag
-      attribute: (jsx_attribute
-        [
-          (property_identifier)
-          (jsx_namespace_name)
-        ] @attr
-        [
-          (jsx_expression (_) @value)
-          (string (string_fragment) @value)
-        ]*
-      )*
+      attribute: [
+        (jsx_attribute
+          [
+            (property_identifier)
+            (jsx_namespace_name)
+          ] @attr
+          [
+            (jsx_expression (_) @value)
+            (string (string_fragment) @value)
+          ]*
+        )
+        (jsx_expression (spread_element (_) @spread))
+      ]*
     )
     (_)* @children
     close_tag: (_)
@@ -25,15 +28,18 @@
 
   (jsx_self_closing_element
     name: (_) @tag
-    attribute: (jsx_attribute
-      [
-        (property_identifier)
-        (jsx_namespace_name)
-      ] @attr
-      [
-        (jsx_expression (_) @value)
-        (string (string_fragment) @value)
-      ]*
-    )*
+    attribute: [
+      (jsx_attribute
+        [
+          (property_identifier)
+          (jsx_namespace_name)
+        ] @attr
+        [
+          (jsx_expression (_) @value)
+          (string (string_fragment) @value)
+        ]*
+      )
+      (jsx_expression (spread_element (_) @spread))
+    ]*
   )
 ] @element
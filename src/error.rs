@@ -15,6 +15,8 @@ pub enum ParserError {
   ParseMsg { ln: usize, col: usize, msg: &'static str },
   #[error("Missing directory path")]
   MissingDir,
+  #[error("Unknown output style \"{0}\", expected \"default\", \"compact\" or \"debug\"")]
+  UnknownOutputStyle(String),
   #[error(transparent)]
   Fmt(#[from] fmt::Error),
   #[error(transparent)]
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+
+/// Where a piece of text ends up in the generated code, each one has its own set of
+/// characters that would otherwise break out of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Context {
+  /// Inside a JS template literal (`` `...` ``), escapes `` ` ``, `\` and `${`.
+  TemplateLiteral,
+  /// Inside a JS double quoted string, escapes `"`, `\` and line terminators.
+  JsString,
+  /// Inside a double quoted HTML attribute value, escapes `"`.
+  /// `&` is left as is so character references keep working like they do in JSX.
+  HtmlAttribute,
+  /// HTML text content, escapes `<` and `>`.
+  /// `&` is left as is so character references keep working like they do in JSX.
+  HtmlText,
+}
+
+fn replacement(ctx: Context, c: char, next: Option<char>) -> Option<&'static str> {
+  match ctx {
+    Context::TemplateLiteral => match c {
+      '`' => Some("\\`"),
+      '\\' => Some("\\\\"),
+      '$' if next == Some('{') => Some("\\$"),
+      _ => None,
+    },
+    Context::JsString => match c {
+      '"' => Some("\\\""),
+      '\\' => Some("\\\\"),
+      '\n' => Some("\\n"),
+      '\r' => Some("\\r"),
+      '\u{2028}' => Some("\\u2028"),
+      '\u{2029}' => Some("\\u2029"),
+      _ => None,
+    },
+    Context::HtmlAttribute => match c {
+      '"' => Some("&quot;"),
+      _ => None,
+    },
+    Context::HtmlText => match c {
+      '<' => Some("&lt;"),
+      '>' => Some("&gt;"),
+      _ => None,
+    },
+  }
+}
+
+/// Escapes `s` so it can be pasted verbatim in `ctx`, borrows when nothing needs escaping.
+pub(super) fn escape(s: &str, ctx: Context) -> Cow<'_, str> {
+  let mut chars = s.char_indices().peekable();
+  let mut ret: Option<String> = None;
+  let mut last = 0;
+
+  while let Some((i, c)) = chars.next() {
+    let Some(r) = replacement(ctx, c, chars.peek().map(|(_, c)| *c)) else {
+      continue;
+    };
+
+    let ret = ret.get_or_insert_with(|| String::with_capacity(s.len() + 16));
+    ret.push_str(&s[last..i]);
+    ret.push_str(r);
+    last = i + c.len_utf8();
+  }
+
+  match ret {
+    Some(mut ret) => {
+      ret.push_str(&s[last..]);
+      Cow::Owned(ret)
+    }
+    None => Cow::Borrowed(s),
+  }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+  use super::super::escape::{escape, Context};
+
+  const AWKWARD: &[char] = &[
+    '`', '$', '{', '}', '\\', '"', '\'', '<', '>', '&', '/', '\n', '\r', '\t', ' ', 'a', '\u{2028}', '\u{2029}',
+    '\u{A0}', '😊',
+  ];
+
+  /// Every string up to `len` characters long made out of [`AWKWARD`] characters.
+  fn awkward_strings(len: usize) -> Vec<String> {
+    let mut ret = vec![String::new()];
+    let mut last = vec![String::new()];
+    for _ in 0..len {
+      last = last
+        .iter()
+        .flat_map(|s| AWKWARD.iter().map(move |c| format!("{s}{c}")))
+        .collect();
+      ret.extend_from_slice(&last);
+    }
+    ret
+  }
+
+  /// Evaluates a JS template literal/string body the way a JS engine would, but only for
+  /// the escape sequences [`escape`] is allowed to produce.
+  fn unescape_js(s: &str, quote: char) -> Result<String, String> {
+    let mut ret = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+      match c {
+        '\\' => match chars.next() {
+          Some('n') => ret.push('\n'),
+          Some('r') => ret.push('\r'),
+          Some('u') => {
+            let code: String = chars.by_ref().take(4).collect();
+            let code = u32::from_str_radix(&code, 16).map_err(|e| format!("{e} in {s:?}"))?;
+            ret.push(char::from_u32(code).ok_or_else(|| format!("invalid code in {s:?}"))?);
+          }
+          Some(c @ ('\\' | '`' | '$' | '"')) => ret.push(c),
+          c => return Err(format!("Unexpected escape {c:?} in {s:?}")),
+        },
+        c if c == quote => return Err(format!("Unescaped {quote} in {s:?}")),
+        '$' if quote == '`' && chars.peek() == Some(&'{') => return Err(format!("Unescaped ${{ in {s:?}")),
+        '\n' | '\r' if quote == '"' => return Err(format!("Unescaped line terminator in {s:?}")),
+        c => ret.push(c),
+      }
+    }
+    Ok(ret)
+  }
+
+  #[test]
+  fn test_template_literal_roundtrip() {
+    for s in awkward_strings(3) {
+      let escaped = escape(&s, Context::TemplateLiteral);
+      assert_eq!(unescape_js(&escaped, '`').as_deref(), Ok(s.as_str()), "Output: {escaped:?}");
+    }
+  }
+
+  #[test]
+  fn test_js_string_roundtrip() {
+    for s in awkward_strings(3) {
+      let escaped = escape(&s, Context::JsString);
+      assert_eq!(unescape_js(&escaped, '"').as_deref(), Ok(s.as_str()), "Output: {escaped:?}");
+    }
+  }
+
+  #[test]
+  fn test_html_attribute_never_closes_quote() {
+    for s in awkward_strings(3) {
+      let escaped = escape(&s, Context::HtmlAttribute);
+      assert!(!escaped.contains('"'), "Output: {escaped:?}");
+      assert_eq!(escaped.replace("&quot;", "\""), s, "Output: {escaped:?}");
+    }
+  }
+
+  #[test]
+  fn test_html_text_never_opens_tag() {
+    for s in awkward_strings(3) {
+      let escaped = escape(&s, Context::HtmlText);
+      assert!(!escaped.contains(['<', '>']), "Output: {escaped:?}");
+      assert_eq!(escaped.replace("&lt;", "<").replace("&gt;", ">"), s, "Output: {escaped:?}");
+    }
+  }
+
+  #[test]
+  fn test_html_then_template_literal() {
+    for s in awkward_strings(3) {
+      let html = escape(&s, Context::HtmlAttribute);
+      let escaped = escape(&html, Context::TemplateLiteral);
+      assert_eq!(unescape_js(&escaped, '`').as_deref(), Ok(&*html), "Output: {escaped:?}");
+    }
+  }
+
+  #[test]
+  fn test_borrows_when_nothing_to_escape() {
+    for ctx in [
+      Context::TemplateLiteral,
+      Context::JsString,
+      Context::HtmlAttribute,
+      Context::HtmlText,
+    ] {
+      assert!(matches!(escape("plain text 😊 $ {}", ctx), std::borrow::Cow::Borrowed(_)), "{ctx:?}");
+    }
+  }
+
+  #[test]
+  fn test_character_references_are_kept() {
+    assert_eq!(escape("a &amp; b &quot;", Context::HtmlAttribute), "a &amp; b &quot;");
+    assert_eq!(escape("a &lt; b", Context::HtmlText), "a &lt; b");
+  }
+}
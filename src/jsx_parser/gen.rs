@@ -1,11 +1,14 @@
 use tree_sitter::Node;
 
 use super::{
+  escape::{escape, Context},
   utils::{
-    generate_event_var, is_jsx_element, is_jsx_text, is_reactive_kind, is_static_kind, merge_jsx_text,
-    replace_jsx, wrap_reactive_value, GlobalState,
+    generate_event_var, is_delegatable_event, is_jsx_element, is_jsx_text, is_namespaced_attribute, is_reactive_kind, is_static_kind,
+    merge_jsx_text,
+    replace_jsx, EventModifiers, MODIFIER_SEPARATOR, static_prop_value, template_attribute_value, wrap_reactive_value, GlobalState,
   },
-  Child, JsxTemplate, VAR_PREF,
+  style::OutputStyle,
+  Branch, Child, JsxTemplate, Prop, TemplateParts, CONSTANT_KIND, SPREAD_KEY, VAR_PREF,
 };
 use crate::error::ParserError;
 use std::{borrow::Cow, fmt::Write};
@@ -18,7 +21,12 @@ impl<'a> JsxTemplate<'a> {
     let mut classes: Option<Vec<&str>> = None;
     let mut styles: Option<Vec<Box<str>>> = None;
 
-    for prop in &self.props {
+    // Static attributes after a spread must be applied at runtime so they can override it
+    let first_spread = self.props.iter().position(|p| p.key == SPREAD_KEY).unwrap_or(self.props.len());
+
+    // Props on <slot> are passed to the slot function instead
+    let attributes = if self.tag == "slot" { &[][..] } else { &self.props[..first_spread] };
+    for prop in attributes {
       if !is_static_kind(prop.kind) || prop.key.starts_with('$') {
         continue;
       }
@@ -33,9 +41,11 @@ impl<'a> JsxTemplate<'a> {
         styles.push(
           format!(
             "{property}:{};",
-            prop
-              .value
-              .ok_or_else(|| ParserError::msg("\"style:*\" JSX properties must have a value", prop.node))?
+            escape(
+              &template_attribute_value(prop)
+                .ok_or_else(|| ParserError::msg("\"style:*\" JSX properties must have a value", prop.node))?,
+              Context::HtmlAttribute
+            )
           )
           .into(),
         );
@@ -45,22 +55,24 @@ impl<'a> JsxTemplate<'a> {
         styles.push(
           format!(
             "--{custom_property}:{};",
-            prop
-              .value
-              .ok_or_else(|| ParserError::msg("\"var:*\" JSX properties must have a value", prop.node))?
+            escape(
+              &template_attribute_value(prop)
+                .ok_or_else(|| ParserError::msg("\"var:*\" JSX properties must have a value", prop.node))?,
+              Context::HtmlAttribute
+            )
           )
           .into(),
         );
-      } else if prop.key != "slot" {
-        write!(f, " {}", prop.key)?;
-        if let Some(v) = prop.value {
-          write!(f, "=\"{v}\"")?;
+      } else if prop.key != "slot" && !prop.key.starts_with("prop:") && !prop.key.starts_with("use:") {
+        write!(f, " {}", prop.key.strip_prefix("attr:").unwrap_or(prop.key))?;
+        if let Some(v) = template_attribute_value(prop) {
+          write!(f, "=\"{}\"", escape(&v, Context::HtmlAttribute))?;
         }
       }
     }
 
     if let Some(classes) = classes.take() {
-      write!(f, " class=\"{}\"", classes.join(" "))?;
+      write!(f, " class=\"{}\"", escape(&classes.join(" "), Context::HtmlAttribute))?;
     }
     if let Some(styles) = styles.take() {
       write!(f, " style=\"{}\"", styles.join(""))?;
@@ -72,6 +84,11 @@ impl<'a> JsxTemplate<'a> {
     }
 
     write!(f, ">")?;
+    if self.switch.is_some() {
+      write!(f, "<!></{}>", self.tag)?;
+      return Ok(f);
+    }
+
     let mut idx = 0;
     while let Some(child) = self.children.get(idx) {
       if is_jsx_element(child.kind) {
@@ -80,21 +97,37 @@ impl<'a> JsxTemplate<'a> {
           continue;
         };
 
+        if elem.conditional.is_some() || elem.transition.is_some() {
+          let (branches, end) = self.branch_group(idx, elem, templates)?;
+          if !branches.is_empty() {
+            write!(f, "<!>")?;
+            idx = end;
+            continue;
+          }
+        }
+
         if elem.is_component()
           || elem.conditional.is_some()
           || elem.transition.is_some()
+          || elem.key.is_some()
           || elem.tag == "template"
+          || elem.has_fallback()
         {
           write!(f, "<!>")?;
         } else {
           write!(f, "{}", elem.generate_template_string(templates)?)?;
         }
       } else if is_jsx_text(child.kind) {
-        let text = merge_jsx_text(&self.children, &mut idx, false)?;
+        let is_first = idx == 0;
+        let text = merge_jsx_text(&self.children, &mut idx, false, self.preserve_whitespace)?;
         if text.is_empty() {
           continue;
         }
-        write!(f, "{}", text)?;
+        // The HTML parser drops a newline right after these opening tags
+        if is_first && text.starts_with('\n') && matches!(self.tag, "pre" | "textarea" | "listing") {
+          writeln!(f)?;
+        }
+        write!(f, "{}", escape(&text, Context::HtmlText))?;
         idx -= 1;
       } else {
         write!(f, "<!>")?;
@@ -114,12 +147,30 @@ impl<'a> JsxTemplate<'a> {
     state: &mut GlobalState,
   ) -> Result<Option<Cow<'a, str>>, ParserError> {
     Ok(if is_jsx_text(child.kind) {
-      let escaped = merge_jsx_text(&self.children, idx, true)?;
+      let escaped = merge_jsx_text(&self.children, idx, true, self.preserve_whitespace)?;
       (escaped != "\"\"").then_some(Cow::Owned(escaped))
     } else {
+      if let Some(elem) = templates.iter().find(|t| is_jsx_element(child.kind) && *t == child) {
+        elem.check_branch()?;
+        if elem.transition.is_some() {
+          self.branch_group(*idx, elem, templates)?;
+        }
+        if let Some(cond) = &elem.conditional {
+          let (branches, end) = self.branch_group(*idx, elem, templates)?;
+          if !branches.is_empty() {
+            *idx = end;
+            let selector = if_chain_selector(cond, &branches, templates, state)?;
+            let branches = [elem].into_iter().chain(branches).collect::<Vec<_>>();
+            let render = generate_branch_render("document.createComment(\"\")", &selector, &branches, templates, state)?;
+            // Named slots strip the call off immediately invoked values
+            return Ok(Some(Cow::Owned(format!("(() => {render})()"))));
+          }
+        }
+      }
+
       *idx += 1;
       state.is_component_child = true;
-      let ret = replace_jsx(child.node, templates, child.value, state)?;
+      let ret = replace_jsx(child.expr, templates, child.value, state)?;
       state.is_component_child = false;
       Some(ret)
     })
@@ -129,12 +180,24 @@ impl<'a> JsxTemplate<'a> {
     &self,
     templates: &[JsxTemplate],
     state: &mut GlobalState,
-  ) -> Result<(String, String), ParserError> {
-    let mut s = format!("{}.$$slots = ", self.tag);
+  ) -> Result<String, ParserError> {
+    if let Some(switch) = &self.switch {
+      return Err(ParserError::msg("\"$switch\" only works on elements", switch.node));
+    }
+    if let Some(prop) = self.props.iter().find(|p| {
+      ["use:", "bind:", "prop:", "attr:"]
+        .iter()
+        .any(|directive| p.key.starts_with(directive))
+    }) {
+      return Err(ParserError::msg(
+        "\"use:\", \"bind:\", \"prop:\" and \"attr:\" directives only work on elements",
+        prop.node,
+      ));
+    }
 
-    if self.children.is_empty() {
-      write!(s, "{{}}")?;
-    } else if !self.children.is_empty() {
+    // Slots are a fresh object on every call so recursive and re-entrant renders don't share them
+    let mut s = String::new();
+    if !self.children.is_empty() {
       write!(s, "{{")?;
       let mut default_slot: Option<Vec<Cow<str>>> = None;
       let mut idx = 0;
@@ -155,15 +218,15 @@ impl<'a> JsxTemplate<'a> {
             continue;
           };
 
-          write!(
-            s,
-            "{}: {}, ",
-            slot
-              .value
-              .ok_or_else(|| ParserError::msg("\"slot\" attribute must have a value", child.node))?,
-            &value[..value.len() - 2]
-          )?;
-        } else if is_reactive_kind(child.kind) {
+          let name = slot
+            .value
+            .ok_or_else(|| ParserError::msg("\"slot\" attribute must have a value", child.node))?;
+          if elem.lets.is_empty() {
+            write!(s, "{name}: {}, ", &value[..value.len() - 2])?;
+          } else {
+            write!(s, "{name}: {}, ", elem.scoped_slot(&value)?)?;
+          }
+        } else if is_reactive_kind(child.kind) && !child.is_static {
           let slot = default_slot.get_or_insert_with(|| Vec::with_capacity(10));
           slot.push(Cow::Owned(format!("() => {}", value)));
         } else {
@@ -172,18 +235,46 @@ impl<'a> JsxTemplate<'a> {
         }
       }
       if let Some(slot) = default_slot {
-        write!(s, "default: () => [{}]", slot.join(","))?;
+        let slot = format!("[{}]", slot.join(","));
+        if self.lets.is_empty() {
+          write!(s, "default: () => {slot}")?;
+        } else {
+          write!(s, "default: {}", self.scoped_slot(&slot)?)?;
+        }
       }
       write!(s, "}}")?;
     }
 
     let mut f = format!("{}(", self.tag);
+    let has_spread = self.props.iter().any(|p| p.key == SPREAD_KEY);
 
     if self.props.is_empty() {
       write!(f, "{{}}")?;
     } else {
+      if has_spread {
+        state.imports.insert("mergeProps");
+        write!(f, "{VAR_PREF}mergeProps(")?;
+      }
       write!(f, "{{")?;
       for prop in &self.props {
+        if matches!(prop.key, "$ref" | "$refFn") {
+          continue;
+        }
+        if prop.key == SPREAD_KEY {
+          let value = replace_jsx(prop.node, templates, prop.value.unwrap_or("{}"), state)?;
+          write!(f, "}}, () => ({value}), {{")?;
+          continue;
+        }
+
+        if (prop.key.starts_with("on:") || prop.key.starts_with("g:on"))
+          && prop.key.contains(MODIFIER_SEPARATOR as char)
+        {
+          return Err(ParserError::msg(
+            "Event modifiers are only supported on elements, components receive on: props as is",
+            prop.node,
+          ));
+        }
+
         let key = if prop.key.contains(':') {
           Cow::Owned(format!("\"{}\"", prop.key))
         } else {
@@ -195,10 +286,13 @@ impl<'a> JsxTemplate<'a> {
             f,
             "{}: \"{}\", ",
             key,
-            prop.value.ok_or_else(|| ParserError::msg(
-              "\"string_fragment\" prop kind must have a value",
-              prop.node
-            ))?
+            escape(
+              prop.value.ok_or_else(|| ParserError::msg(
+                "\"string_fragment\" prop kind must have a value",
+                prop.node
+              ))?,
+              Context::JsString
+            )
           )?;
         } else if !(prop.key.starts_with("on:") || prop.key.starts_with("g:on"))
           && is_reactive_kind(prop.kind)
@@ -227,19 +321,198 @@ impl<'a> JsxTemplate<'a> {
         }
       }
       write!(f, "}}")?;
+      if has_spread {
+        write!(f, ")")?;
+      }
     }
     if !self.children.is_empty() {
-      write!(f, ", {}.$$slots", self.tag)?;
+      write!(f, ", {s}")?;
     }
     write!(f, ")")?;
 
-    Ok((s, f))
+    Ok(f)
+  }
+
+  fn is_custom_element(&self) -> bool {
+    !self.is_component() && self.tag.contains('-')
+  }
+
+  /// Assigns a DOM property, tracking it when `kind` is reactive.
+  fn write_property(
+    &self,
+    elem_setup: &mut String,
+    var: &str,
+    key: &str,
+    kind: &str,
+    value: &str,
+    state: &mut GlobalState,
+  ) -> Result<(), ParserError> {
+    if is_reactive_kind(kind) {
+      state.imports.insert("trackProperty");
+      writeln!(elem_setup, "{VAR_PREF}trackProperty({var}, \"{key}\", () => {value});")?;
+    } else {
+      writeln!(elem_setup, "{var}[\"{key}\"] = {value};")?;
+    }
+
+    Ok(())
+  }
+
+  /// Two-way `bind:` directives, `value` is either a `[getter, setter]` tuple or an assignable expression.
+  /// An identifier must hold a tuple, like the one `ref()` returns, except for `bind:this` which assigns to it.
+  fn write_binding(
+    &self,
+    elem_setup: &mut String,
+    var: &str,
+    target: &str,
+    prop: &Prop,
+    value: &str,
+    state: &mut GlobalState,
+  ) -> Result<(), ParserError> {
+    let assignable = matches!(prop.kind, "member_expression" | "subscript_expression");
+    let binding = match prop.kind {
+      "array" | "identifier" => Cow::Borrowed(value),
+      _ if assignable => Cow::Owned(format!("[() => {value}, ({VAR_PREF}v) => {value} = {VAR_PREF}v]")),
+      _ => {
+        return Err(ParserError::msg(
+          "bind: expects a [getter, setter] tuple or an assignable expression",
+          prop.node,
+        ))
+      }
+    };
+
+    match target {
+      "value" | "checked" => {
+        let event = if target == "checked" || self.tag == "select" { "change" } else { "input" };
+        state.imports.insert("bindProperty");
+        writeln!(elem_setup, "{VAR_PREF}bindProperty({var}, \"{target}\", \"{event}\", {binding});")?;
+      }
+      "group" => {
+        state.imports.insert("bindGroup");
+        writeln!(elem_setup, "{VAR_PREF}bindGroup({var}, {binding});")?;
+      }
+      "this" if assignable || prop.kind == "identifier" => writeln!(elem_setup, "{value} = {var};")?,
+      "this" => writeln!(elem_setup, "({value})[1]({var});")?,
+      _ => {
+        return Err(ParserError::msg(
+          "Unknown bind: directive, expected one of bind:value, bind:checked, bind:group or bind:this",
+          prop.node,
+        ))
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Keys explicitly set after the spread at `idx`, reactive spreads must not overwrite them. Events
+  /// are listed without their modifiers so the spread doesn't add a second listener.
+  fn spread_overrides(&self, idx: usize) -> String {
+    self.props[idx + 1..]
+      .iter()
+      .filter(|p| p.key != SPREAD_KEY && p.key != "slot" && !matches!(p.key, "$ref" | "$refFn"))
+      .map(|p| {
+        let key = p.key.strip_prefix('$').unwrap_or(p.key);
+        let key = if key.starts_with("on:") || key.starts_with("g:on") {
+          key.split(MODIFIER_SEPARATOR as char).next().unwrap_or(key)
+        } else {
+          key
+        };
+        format!("\"{key}\"")
+      })
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+
+  /// Tracked props are folded into a spread written after them, so the spread's keys win like they
+  /// would in source order instead of whichever effect runs last.
+  fn is_folded_into_spread(&self, idx: usize, state: &GlobalState) -> bool {
+    let prop = &self.props[idx];
+    let tracked = match prop.key.split_once(':') {
+      Some(("class" | "style" | "var" | "prop" | "attr", _)) => true,
+      Some(_) => false,
+      None => prop.key.starts_with('$') && !matches!(prop.key, "$ref" | "$refFn"),
+    };
+    tracked
+      && is_reactive_kind(prop.kind)
+      && !(self.is_custom_element() && state.options.custom_element_props)
+      && self.props[idx + 1..].iter().any(|p| p.key == SPREAD_KEY)
+  }
+
+  /// Slot function declaring the `let:` props as getters on the props object the slot is called with.
+  fn scoped_slot(&self, value: &str) -> Result<String, ParserError> {
+    let mut f = format!("({VAR_PREF}slotProps) => {{\n");
+    for prop in &self.lets {
+      let key = prop.key.trim_start_matches("let:");
+      let local = prop.value.unwrap_or(key);
+      writeln!(f, "const {local} = () => {VAR_PREF}slotProps[\"{key}\"];")?;
+    }
+    write!(f, "return {value};\n}}")?;
+
+    Ok(f)
+  }
+
+  /// Props object a scoped `<slot>` passes to the slot function, every value is read through a getter.
+  fn slot_props(&self, templates: &[JsxTemplate], state: &mut GlobalState) -> Result<String, ParserError> {
+    let mut f = String::new();
+    for prop in self.props.iter().filter(|p| p.key != "name") {
+      if prop.key == SPREAD_KEY {
+        return Err(ParserError::msg("Spread props aren't supported on <slot>", prop.node));
+      }
+
+      match (prop.kind, prop.value) {
+        (_, None) => write!(f, "\"{}\": true, ", prop.key)?,
+        ("string_fragment", Some(value)) => {
+          write!(f, "\"{}\": \"{}\", ", prop.key, escape(value, Context::JsString))?
+        }
+        (_, Some(value)) => {
+          let value = replace_jsx(prop.node, templates, value, state)?;
+          write!(f, "get \"{}\"() {{ return {value} }}, ", prop.key)?;
+        }
+      }
+    }
+
+    Ok(if f.is_empty() { f } else { format!("{{{f}}}") })
+  }
+
+  /// Children as an array of values, used for `<template>` fragments and slot fallbacks.
+  pub(super) fn generate_fragment(
+    &self,
+    templates: &[JsxTemplate],
+    state: &mut GlobalState,
+  ) -> Result<String, ParserError> {
+    let mut f = String::from("[");
+    let mut idx = 0;
+    while let Some(c) = self.children.get(idx) {
+      state.is_template_child = is_jsx_element(c.kind);
+      let Some(value) = self.child_as_value(&mut idx, c, templates, state)? else {
+        continue;
+      };
+
+      if is_reactive_kind(c.kind) && !c.is_static {
+        write!(f, "() => {value}, ")?;
+      } else {
+        write!(f, "{value}, ")?;
+      }
+    }
+    writeln!(f, "]")?;
+
+    Ok(f)
+  }
+
+  /// A `<slot>` with children other than whitespace renders them when the slot isn't passed.
+  pub(super) fn has_fallback(&self) -> bool {
+    self.tag == "slot"
+      && !self.is_root
+      && self
+        .children
+        .iter()
+        .any(|c| !is_jsx_text(c.kind) || !c.value.trim().is_empty())
   }
 
   pub(super) fn replace_slot(
     &self,
     elem_setup: &mut String,
     var: &str,
+    templates: &[JsxTemplate],
     state: &mut GlobalState,
     node: Option<&Node>,
   ) -> Result<(), ParserError> {
@@ -260,16 +533,204 @@ impl<'a> JsxTemplate<'a> {
       .transpose()?
       .unwrap_or("default");
 
+    let slots = &self.slots.as_ref().ok_or(ParserError::Parse)?.name;
     state.imports.insert("insertChild");
-    // by using `arguments` here <slot> will only work on components defined as `function()`
-    writeln!(
-      elem_setup,
-      "{VAR_PREF}insertChild({var}, arguments[1]?.[\"{name}\"]?.());"
-    )?;
+    let props = self.slot_props(templates, state)?;
+    if self.has_fallback() {
+      // Fallback content is only created when the caller doesn't pass the slot
+      let fallback = self.generate_fragment(templates, state)?;
+      writeln!(
+        elem_setup,
+        "{VAR_PREF}insertChild({var}, {slots}?.[\"{name}\"]?.({props}) ?? {});",
+        fallback.trim_end()
+      )?;
+    } else {
+      writeln!(elem_setup, "{VAR_PREF}insertChild({var}, {slots}?.[\"{name}\"]?.({props}));")?;
+    }
 
     Ok(())
   }
 
+  /// `$elseif`/`$else` and `$case`/`$default` elements are only generated as part of their group.
+  fn check_branch(&self) -> Result<(), ParserError> {
+    match &self.branch {
+      Some(Branch::ElseIf(prop) | Branch::Else(prop)) => Err(ParserError::msg(
+        "\"$elseif\" and \"$else\" must follow an element with \"$if\" or \"$elseif\"",
+        prop.node,
+      )),
+      Some(Branch::Case(prop) | Branch::Default(prop)) => Err(ParserError::msg(
+        "\"$case\" and \"$default\" must be used on direct children of an element with \"$switch\"",
+        prop.node,
+      )),
+      None => Ok(()),
+    }
+  }
+
+  /// `$elseif`/`$else` siblings following the `$if` child at `idx`, whitespace between them is dropped.
+  /// Returns them with the index right after the group. `head` is the `$if` child, its transition
+  /// would only run for the first branch.
+  fn branch_group<'t>(
+    &self,
+    idx: usize,
+    head: &JsxTemplate,
+    templates: &'t [JsxTemplate<'a>],
+  ) -> Result<(Vec<&'t JsxTemplate<'a>>, usize), ParserError> {
+    let mut branches = Vec::new();
+    let mut end = idx + 1;
+    let mut i = idx + 1;
+
+    while let Some(child) = self.children.get(i) {
+      i += 1;
+      if is_jsx_text(child.kind) && child.value.trim().is_empty() {
+        continue;
+      }
+      let Some(elem) = templates.iter().find(|t| is_jsx_element(child.kind) && *t == child) else {
+        break;
+      };
+
+      match elem.branch {
+        Some(Branch::ElseIf(_)) => branches.push(elem),
+        Some(Branch::Else(_)) => {
+          branches.push(elem);
+          end = i;
+          break;
+        }
+        _ => break,
+      }
+      end = i;
+    }
+
+    match &head.transition {
+      Some((_, transition)) if !branches.is_empty() => Err(ParserError::msg(
+        "\"$transition\" can't be used on an \"$if\" followed by \"$elseif\" or \"$else\"",
+        transition.node,
+      )),
+      _ => Ok((branches, end)),
+    }
+  }
+
+  /// Selector picking the `$case` child equal to the `$switch` value, whitespace between children is dropped.
+  fn switch_selector<'t>(
+    &self,
+    switch: &Prop,
+    templates: &'t [JsxTemplate<'a>],
+    state: &mut GlobalState,
+  ) -> Result<(String, Vec<&'t JsxTemplate<'a>>), ParserError> {
+    let mut selector = format!(
+      "() => {{ const {VAR_PREF}v = {}; return ",
+      directive_value(switch, "undefined", templates, state)?
+    );
+    let mut branches = Vec::new();
+    let mut default = None;
+
+    for child in &self.children {
+      if is_jsx_text(child.kind) && child.value.trim().is_empty() {
+        continue;
+      }
+      let elem = templates
+        .iter()
+        .find(|t| is_jsx_element(child.kind) && *t == child)
+        .ok_or_else(|| ParserError::msg("Children of \"$switch\" must be elements with \"$case\" or \"$default\"", child.node))?;
+
+      match &elem.branch {
+        Some(Branch::Case(case)) => {
+          let case = directive_value(case, "undefined", templates, state)?;
+          write!(selector, "{VAR_PREF}v === ({case}) ? {} : ", branches.len())?;
+        }
+        Some(Branch::Default(_)) if default.is_none() => default = Some(branches.len()),
+        Some(Branch::Default(prop)) => {
+          return Err(ParserError::msg("\"$switch\" can only have one \"$default\"", prop.node))
+        }
+        _ => {
+          return Err(ParserError::msg(
+            "Children of \"$switch\" must be elements with \"$case\" or \"$default\"",
+            child.node,
+          ))
+        }
+      }
+      branches.push(elem);
+    }
+
+    match default {
+      Some(i) => write!(selector, "{i}; }}")?,
+      None => write!(selector, "-1; }}")?,
+    }
+
+    Ok((selector, branches))
+  }
+
+  /// `is_branch` components are part of an `$if` chain that already handles their `$if`.
+  fn has_component_directives(&self, is_branch: bool) -> bool {
+    (self.conditional.is_some() && !is_branch)
+      || self.transition.is_some()
+      || self.key.is_some()
+      || self.show.is_some()
+      || self.props.iter().any(|p| matches!(p.key, "$ref" | "$refFn"))
+  }
+
+  /// Component call with `$show`/`$ref`/`$refFn` applied to the node it returns, rendered in place
+  /// of `anchor` when `$if`, `$transition` or `$key` is used.
+  fn generate_wrapped_component(
+    &self,
+    anchor: &str,
+    is_branch: bool,
+    templates: &[JsxTemplate],
+    state: &mut GlobalState,
+  ) -> Result<String, ParserError> {
+    let is_component_child = state.is_component_child;
+    let call = self.generate_component_call(templates, state)?;
+    state.is_component_child = is_component_child;
+
+    let node = format!("{VAR_PREF}node");
+    // Shown and transitioned nodes get classes and styles, the component must return one element
+    let call = if self.show.is_some() || self.transition.is_some() {
+      state.imports.insert("singleElement");
+      Cow::Owned(format!("{VAR_PREF}singleElement({call}, \"{}\")", self.tag))
+    } else {
+      Cow::Borrowed(&*call)
+    };
+    let mut create = format!("() => {{\nconst {node} = {call};\n");
+    if let Some((cond, transition)) = &self.show {
+      state.imports.insert("trackShow");
+      let cond = wrap_reactive_value(cond.kind, cond.value.unwrap_or("true"));
+      match transition {
+        Some(name) => writeln!(create, "{VAR_PREF}trackShow({node}, {cond}, \"{name}\");")?,
+        None => writeln!(create, "{VAR_PREF}trackShow({node}, {cond});")?,
+      }
+    }
+    for prop in &self.props {
+      match (prop.key, prop.value) {
+        ("$ref", Some(value)) => writeln!(create, "{value} = {node};")?,
+        ("$refFn", Some(value)) => writeln!(create, "{value}({node});")?,
+        ("$ref" | "$refFn", None) => return Err(ParserError::msg("\"$ref\" and \"$refFn\" must have a value", prop.node)),
+        _ => (),
+      }
+    }
+    write!(create, "return {node};\n}}")?;
+
+    Ok(if let Some(cond) = self.conditional.as_ref().filter(|_| !is_branch) {
+      state.imports.insert("conditionalRender");
+      format!(
+        "{VAR_PREF}conditionalRender({anchor}, {create}, {})",
+        wrap_reactive_value(cond.kind, cond.value.unwrap_or("true"))
+      )
+    } else if let Some((name, cond)) = &self.transition {
+      state.imports.insert("createTransition");
+      format!(
+        "{VAR_PREF}createTransition({anchor}, {create}, {}, \"{name}\")",
+        wrap_reactive_value(cond.kind, cond.value.unwrap_or("true"))
+      )
+    } else if let Some(key) = &self.key {
+      state.imports.insert("keyedRender");
+      format!(
+        "{VAR_PREF}keyedRender({anchor}, {create}, () => ({}))",
+        key.value.unwrap_or("undefined")
+      )
+    } else {
+      format!("({create})()")
+    })
+  }
+
   pub(super) fn generate_fn(
     &self,
     var_idx: &mut usize,
@@ -279,22 +740,30 @@ impl<'a> JsxTemplate<'a> {
     let mut elem_vars = String::new();
     let mut var = format!("{VAR_PREF}el{}", *var_idx);
 
+    let is_branch = std::mem::take(&mut state.is_branch);
+    if self.is_root {
+      self.check_branch()?;
+    }
     if self.is_component() {
       let is_component_child = state.is_component_child;
-      let (s, f) = self.generate_component_call(templates, state)?;
+      let component = if self.has_component_directives(is_branch) {
+        self.generate_wrapped_component("document.createComment(\"\")", is_branch, templates, state)?
+      } else {
+        self.generate_component_call(templates, state)?
+      };
 
       if self.is_root || is_component_child {
-        writeln!(elem_vars, "const {var} = {f};")?;
-        return Ok((s, elem_vars));
+        writeln!(elem_vars, "const {var} = {component};")?;
+        return Ok((String::new(), elem_vars));
       }
 
-      return Ok((s, f));
+      return Ok((String::new(), component));
     }
 
     let mut elem_setup = String::new();
 
     if self.tag == "slot" {
-      self.replace_slot(&mut elem_setup, &var, state, None)?;
+      self.replace_slot(&mut elem_setup, &var, templates, state, None)?;
     }
 
     if (self.is_root || state.is_component_child) && !state.parsing_special_root {
@@ -323,6 +792,19 @@ impl<'a> JsxTemplate<'a> {
         )?;
         state.parsing_special_root = false;
 
+        return Ok((elem_setup, elem_vars));
+      } else if let Some(key) = &self.key {
+        state.parsing_special_root = true;
+        state.imports.insert("keyedRender");
+        let parts = self.parts(templates, state)?;
+        writeln!(
+          elem_vars,
+          "const {var} = {VAR_PREF}keyedRender(document.createComment(\"\"), {}, () => ({}));",
+          &parts.create_fn[..parts.create_fn.len() - 2],
+          key.value.unwrap_or("undefined")
+        )?;
+        state.parsing_special_root = false;
+
         return Ok((elem_setup, elem_vars));
       }
     }
@@ -330,26 +812,85 @@ impl<'a> JsxTemplate<'a> {
     if self.is_root
       || state.is_component_child
       || self.conditional.is_some()
+      || self.branch.is_some()
       || self.transition.is_some()
+      || self.key.is_some()
       || state.is_template_child
     {
       state.imports.insert("template");
-      state.templates.insert(self.id);
-      writeln!(
-        elem_vars,
-        "const {var} = {VAR_PREF}templ{}(); // root[{}]/component[{}]/conditional[{}]/transition[{}]/template-child[{}]",
-        self.id,
-        self.is_root,
-        state.is_component_child,
-        self.conditional.is_some(),
-        self.transition.is_some(),
-        state.is_template_child
-      )?;
+      let html = self.generate_template_string(templates)?;
+      let root = self.namespace.root_tag().filter(|root| *root != self.tag);
+      let templ = state.template_var(&html, root)?;
+      match state.options.output_style {
+        OutputStyle::Default => writeln!(
+          elem_vars,
+          "const {var} = {templ}(); // root[{}]/component[{}]/conditional[{}]/transition[{}]/template-child[{}]",
+          self.is_root,
+          state.is_component_child,
+          self.conditional.is_some(),
+          self.transition.is_some(),
+          state.is_template_child
+        )?,
+        OutputStyle::Compact => writeln!(elem_vars, "const {var} = {templ}();")?,
+        OutputStyle::Debug => writeln!(elem_vars, "const {var} = {templ}(); // <{}> line {}", self.tag, self.line)?,
+      }
       state.is_template_child = false;
     }
 
-    for prop in &self.props {
+    // Actions stop on unmount and start again on mount, the events come from the tree observer
+    let has_mount_events = self.props.iter().any(|p| p.key.starts_with("on:mount") || p.key.starts_with("on:unmount"));
+    if !has_mount_events && self.props.iter().any(|p| p.key.starts_with("use:")) {
+      write_tree_observer(&mut elem_setup, &var, false, state)?;
+    }
+
+    let mut after_spread = false;
+    let mut spread_statics: Option<Vec<String>> = None;
+    let mut folded = Vec::new();
+    for (i, prop) in self.props.iter().enumerate() {
+      if prop.key == SPREAD_KEY {
+        if let Some(statics) = spread_statics.take() {
+          writeln!(elem_setup, "{VAR_PREF}spreadAttributes({var}, {{{}}});", statics.join(", "))?;
+        }
+        after_spread = true;
+
+        let mut value = replace_jsx(prop.node, templates, prop.value.unwrap_or("{}"), state)?;
+        if !folded.is_empty() {
+          value = Cow::Owned(format!("{{{}, ...({value})}}", folded.join(", ")));
+          folded.clear();
+        }
+        state.imports.insert("spreadAttributes");
+        writeln!(
+          elem_setup,
+          "{VAR_PREF}spreadAttributes({var}, () => ({value}), [{}]);",
+          self.spread_overrides(i)
+        )?;
+        continue;
+      }
+
       if is_static_kind(prop.kind) {
+        if prop.key.starts_with("bind:") {
+          return Err(ParserError::msg(
+            "bind: expects a [getter, setter] tuple or an assignable expression",
+            prop.node,
+          ));
+        } else if let Some(action) = action_name(prop)? {
+          state.imports.insert("useAction");
+          match prop.value {
+            Some(_) => writeln!(
+              elem_setup,
+              "{VAR_PREF}useAction({var}, {action}, () => {});",
+              static_prop_value(prop)
+            )?,
+            None => writeln!(elem_setup, "{VAR_PREF}useAction({var}, {action});")?,
+          }
+        } else if after_spread && prop.key != "slot" {
+          state.imports.insert("spreadAttributes");
+          spread_statics
+            .get_or_insert_with(|| Vec::with_capacity(4))
+            .push(format!("\"{}\": {}", prop.key, static_prop_value(prop)));
+        } else if let Some(key) = prop.key.strip_prefix("prop:") {
+          writeln!(elem_setup, "{var}[\"{key}\"] = {};", static_prop_value(prop))?;
+        }
         continue;
       }
 
@@ -358,35 +899,69 @@ impl<'a> JsxTemplate<'a> {
       };
       let value = replace_jsx(prop.node, templates, value, state)?;
 
-      if prop.key.contains(':') {
-        if let Some(event_name) = prop.key.strip_prefix("on:") {
-          state.imports.insert("addLocalEvent");
+      if self.is_folded_into_spread(i, state) {
+        folded.push(format!("\"{}\": {value}", prop.key));
+        continue;
+      }
+
+      if prop.key.contains(':') && !is_namespaced_attribute(prop.key) {
+        if let Some(event) = prop.key.strip_prefix("on:") {
+          let (event_name, modifiers) = EventModifiers::parse(event, prop.node)?;
 
           if event_name == "mount" || event_name == "unmount" {
-            state.imports.insert("createMutationObserver");
-            state.imports.insert("observeTree");
+            write_tree_observer(&mut elem_setup, &var, event_name == "mount", state)?;
+          }
+
+          let handler = modifiers.wrap_handler(&value)?;
+          let options = modifiers.listener_options();
+          // Document listeners can be passive, handlers that prevent the default keep their own
+          if state.options.delegate_events
+            && is_delegatable_event(event_name)
+            && !modifiers.prevent
+            && options.is_none()
+            && prop.kind != "array"
+          {
+            state.delegated_events.insert(event_name.into());
+            state.imports.insert("delegateEvent");
+            writeln!(elem_setup, "{var}.$${event_name} = {handler};")?;
+          } else if let Some(options) = options {
+            state.imports.insert("addLocalEvent");
+            writeln!(
+              elem_setup,
+              "{VAR_PREF}addLocalEvent({var}, \"{event_name}\", [{handler}, {options}]);"
+            )?;
+          } else {
+            state.imports.insert("addLocalEvent");
             writeln!(
               elem_setup,
-              "{VAR_PREF}observeTree({VAR_PREF}mutObserver, {var}, {});",
-              event_name == "mount"
+              "{VAR_PREF}addLocalEvent({var}, \"{event_name}\", {handler});"
             )?;
           }
-
-          writeln!(
-            elem_setup,
-            "{VAR_PREF}addLocalEvent({var}, \"{event_name}\", {value});"
-          )?;
-        } else if let Some(event_name) = prop.key.strip_prefix("g:on") {
+        } else if let Some(event) = prop.key.strip_prefix("g:on") {
+          let (event_name, modifiers) = EventModifiers::parse(event, prop.node)?;
+          if modifiers.passive || modifiers.capture {
+            return Err(ParserError::msg(
+              "g:on events share a single listener, they can't use the passive or capture modifiers",
+              prop.node,
+            ));
+          }
           if state.events.insert(event_name.into()) {
             state.imports.insert("createGlobalEvent");
             state.imports.insert("addGlobalEvent");
           }
 
+          let handler = modifiers.wrap_handler(&value)?;
+          let handler = match modifiers.listener_options() {
+            Some(options) => Cow::Owned(format!("[{handler}, {options}]")),
+            None => handler,
+          };
           writeln!(
             elem_setup,
-            "{VAR_PREF}addGlobalEvent(window.{}, {var}, {value});",
+            "{VAR_PREF}addGlobalEvent(window.{}, {var}, {handler});",
             generate_event_var(event_name),
           )?;
+        } else if let Some(class) = prop.key.strip_prefix("class:").filter(|_| !is_reactive_kind(prop.kind)) {
+          writeln!(elem_setup, "{var}.classList.toggle(\"{class}\", !!({value}));")?;
         } else if prop.key.starts_with("class:") {
           let class = prop.key.trim_start_matches("class:");
           state.imports.insert("trackClass");
@@ -411,12 +986,40 @@ impl<'a> JsxTemplate<'a> {
             "{VAR_PREF}trackCssProperty({var}, \"--{custom_property}\", {});",
             wrap_reactive_value(prop.kind, &value)
           )?;
+        } else if let Some(key) = prop.key.strip_prefix("prop:") {
+          self.write_property(&mut elem_setup, &var, key, prop.kind, &value, state)?;
+        } else if let Some(action) = action_name(prop)? {
+          state.imports.insert("useAction");
+          writeln!(elem_setup, "{VAR_PREF}useAction({var}, {action}, () => {value});")?;
+        } else if let Some(target) = prop.key.strip_prefix("bind:") {
+          self.write_binding(&mut elem_setup, &var, target, prop, &value, state)?;
+        } else if let Some(key) = prop.key.strip_prefix("attr:") {
+          if is_reactive_kind(prop.kind) {
+            state.imports.insert("trackAttribute");
+            writeln!(
+              elem_setup,
+              "{VAR_PREF}trackAttribute({var}, \"{key}\", {}, false);",
+              wrap_reactive_value(prop.kind, &value)
+            )?;
+          } else {
+            state.imports.insert("setAttribute");
+            writeln!(elem_setup, "{VAR_PREF}setAttribute({var}, \"{key}\", {value});")?;
+          }
+        } else {
+          return Err(ParserError::msg(
+            "Unknown JSX namespace, expected one of on:, g:on, class:, style:, var:, prop:, attr:, bind:, use:, xlink:, xml: or xmlns:",
+            prop.node,
+          ));
         }
       } else if prop.key == "$ref" {
         writeln!(elem_setup, "{value} = {var};")?;
       } else if prop.key == "$refFn" {
         writeln!(elem_setup, "{value}({var});")?;
-      } else if let Some(key) = prop.key.strip_prefix('$') {
+      } else if self.is_custom_element() && state.options.custom_element_props {
+        let key = prop.key.strip_prefix('$').unwrap_or(prop.key);
+        let kind = if prop.key.starts_with('$') { prop.kind } else { "" };
+        self.write_property(&mut elem_setup, &var, key, kind, &value, state)?;
+      } else if let Some(key) = prop.key.strip_prefix('$').filter(|_| is_reactive_kind(prop.kind)) {
         state.imports.insert("trackAttribute");
         writeln!(
           elem_setup,
@@ -424,6 +1027,9 @@ impl<'a> JsxTemplate<'a> {
           key,
           wrap_reactive_value(prop.kind, &value)
         )?;
+      } else if let Some(key) = prop.key.strip_prefix('$') {
+        state.imports.insert("setAttribute");
+        writeln!(elem_setup, "{VAR_PREF}setAttribute({var}, \"{key}\", {value});")?;
       } else {
         state.imports.insert("setAttribute");
         writeln!(
@@ -434,28 +1040,59 @@ impl<'a> JsxTemplate<'a> {
       }
     }
 
+    if let Some(statics) = spread_statics.take() {
+      writeln!(elem_setup, "{VAR_PREF}spreadAttributes({var}, {{{}}});", statics.join(", "))?;
+    }
+
+    if let Some((cond, transition)) = &self.show {
+      state.imports.insert("trackShow");
+      let cond = wrap_reactive_value(cond.kind, cond.value.unwrap_or("true"));
+      match transition {
+        Some(name) => writeln!(elem_setup, "{VAR_PREF}trackShow({var}, {cond}, \"{name}\");")?,
+        None => writeln!(elem_setup, "{VAR_PREF}trackShow({var}, {cond});")?,
+      }
+    }
+
+    // Everything so far sets up this element
+    if !elem_setup.is_empty() {
+      state.walk.use_var(&var);
+    }
+
+    state.is_component_child = false;
+    if let Some(switch) = &self.switch {
+      *var_idx += 1;
+      let anchor = format!("{VAR_PREF}el{}", *var_idx);
+      let comment = match state.options.output_style {
+        OutputStyle::Compact => String::new(),
+        _ => "$switch".to_string(),
+      };
+      state.walk.step(&anchor, &var, "firstChild", comment);
+      state.walk.use_var(&anchor);
+      let (selector, branches) = self.switch_selector(switch, templates, state)?;
+      let render = generate_branch_render(&anchor, &selector, &branches, templates, state)?;
+      writeln!(elem_setup, "{render};")?;
+
+      return Ok((elem_vars, elem_setup));
+    }
+
     let mut first = true;
     let mut idx = 0;
-    state.is_component_child = false;
     while let Some(child) = self.children.get(idx) {
       *var_idx += 1;
       let prev_var = var;
       var = format!("{VAR_PREF}el{}", *var_idx);
 
-      if first {
-        first = false;
-        writeln!(
-          elem_vars,
-          "const {var} = {prev_var}.firstChild; // {}",
-          child.kind
-        )?;
-      } else {
-        writeln!(
-          elem_vars,
-          "const {var} = {prev_var}.nextSibling; // {}",
-          child.kind
-        )?;
-      }
+      let step = if std::mem::take(&mut first) { "firstChild" } else { "nextSibling" };
+      let comment = match state.options.output_style {
+        OutputStyle::Default => walked_kind(child.kind).to_string(),
+        OutputStyle::Compact => String::new(),
+        OutputStyle::Debug => format!(
+          "{} line {}",
+          self.describe_child(child, templates),
+          child.node.start_position().row + 1
+        ),
+      };
+      state.walk.step(&var, &prev_var, step, comment);
 
       match child.kind {
         "jsx_element" | "jsx_self_closing_element" => {
@@ -464,12 +1101,43 @@ impl<'a> JsxTemplate<'a> {
             continue;
           };
 
-          if elem.is_component() {
-            let (slots, call) = elem.generate_component_call(templates, state)?;
+          elem.check_branch()?;
+          let (branches, end) = match (&elem.conditional, &elem.transition) {
+            (None, None) => (Vec::new(), idx),
+            _ => self.branch_group(idx, elem, templates)?,
+          };
+
+          // Plain elements mark their own node when they have setup, anything else is rendered at it
+          let is_plain = !elem.is_component()
+            && !matches!(elem.tag, "slot" | "template")
+            && elem.conditional.is_none()
+            && elem.transition.is_none()
+            && elem.key.is_none();
+          if !is_plain {
+            state.walk.use_var(&var);
+          }
+
+          if let (Some(cond), false) = (&elem.conditional, branches.is_empty()) {
+            let selector = if_chain_selector(cond, &branches, templates, state)?;
+            let branches = [elem].into_iter().chain(branches).collect::<Vec<_>>();
+            let render = generate_branch_render(&var, &selector, &branches, templates, state)?;
+            writeln!(elem_setup, "{render};")?;
+            idx = end;
+            continue;
+          } else if elem.is_component() && elem.has_component_directives(false) {
+            let component = elem.generate_wrapped_component(&var, false, templates, state)?;
+            if elem.conditional.is_some() || elem.transition.is_some() || elem.key.is_some() {
+              writeln!(elem_setup, "{component};")?;
+            } else {
+              state.imports.insert("insertChild");
+              writeln!(elem_setup, "{VAR_PREF}insertChild({var}, {component});")?;
+            }
+          } else if elem.is_component() {
+            let call = elem.generate_component_call(templates, state)?;
             state.imports.insert("insertChild");
-            writeln!(elem_setup, "{slots};\n{VAR_PREF}insertChild({var}, {call});")?;
+            writeln!(elem_setup, "{VAR_PREF}insertChild({var}, {call});")?;
           } else if elem.tag == "slot" {
-            elem.replace_slot(&mut elem_setup, &var, state, Some(&child.node))?;
+            elem.replace_slot(&mut elem_setup, &var, templates, state, Some(&child.node))?;
           } else if let Some(cond) = &elem.conditional {
             state.imports.insert("conditionalRender");
             let parts = elem.parts(templates, state)?;
@@ -497,6 +1165,15 @@ impl<'a> JsxTemplate<'a> {
               &parts.create_fn[..parts.create_fn.len() - 2],
               wrap_reactive_value(cond.kind, cond.value.unwrap_or("true"))
             )?;
+          } else if let Some(key) = &elem.key {
+            state.imports.insert("keyedRender");
+            let parts = elem.parts(templates, state)?;
+            writeln!(
+              elem_setup,
+              "{VAR_PREF}keyedRender({var}, {}, () => ({}));",
+              &parts.create_fn[..parts.create_fn.len() - 2],
+              key.value.unwrap_or("undefined")
+            )?;
           } else if elem.tag == "template" {
             state.imports.insert("insertChild");
             let parts = elem.parts(templates, state)?;
@@ -508,14 +1185,9 @@ impl<'a> JsxTemplate<'a> {
           }
         }
         "jsx_expression" => {
-          let value = replace_jsx(child.node, templates, child.value, state)?;
-          if is_reactive_kind(
-            child
-              .node
-              .named_child(0)
-              .ok_or_else(|| ParserError::empty_jsx_expression(child.node))?
-              .kind(),
-          ) {
+          state.walk.use_var(&var);
+          let value = replace_jsx(child.expr, templates, child.value, state)?;
+          if !child.is_static && is_reactive_kind(child.expr.kind()) {
             state.imports.insert("insertChild");
             writeln!(elem_setup, "{VAR_PREF}insertChild({var}, () => {});", value)?;
           } else {
@@ -541,3 +1213,113 @@ impl<'a> JsxTemplate<'a> {
     Ok((elem_vars, elem_setup))
   }
 }
+
+impl JsxTemplate<'_> {
+  /// What a walked child is in the source, for debug comments.
+  fn describe_child(&self, child: &Child, templates: &[JsxTemplate]) -> String {
+    match templates.iter().find(|t| is_jsx_element(child.kind) && *t == child) {
+      Some(elem) if elem.tag == "template" => "<>".to_string(),
+      Some(elem) => format!("<{}>", elem.tag),
+      None if is_jsx_text(child.kind) => "text".to_string(),
+      None => "{expression}".to_string(),
+    }
+  }
+}
+
+/// Dispatches `mount` and `unmount` events to the element, `mount` also fires once it's first inserted
+/// when `is_mount` is set.
+fn write_tree_observer(out: &mut String, var: &str, is_mount: bool, state: &mut GlobalState) -> Result<(), ParserError> {
+  state.imports.insert("createMutationObserver");
+  state.imports.insert("observeTree");
+  writeln!(out, "{VAR_PREF}observeTree({VAR_PREF}mutObserver, {var}, {is_mount});")?;
+  Ok(())
+}
+
+/// Action of a `use:` key, it's referenced as is so `use:click-outside` can't be used.
+fn action_name<'p>(prop: &Prop<'p>) -> Result<Option<&'p str>, ParserError> {
+  let Some(action) = prop.key.strip_prefix("use:") else {
+    return Ok(None);
+  };
+  let is_start = |c: char| c.is_alphabetic() || c == '_' || c == '$';
+  if action.starts_with(is_start) && action.chars().all(|c| is_start(c) || c.is_ascii_digit()) {
+    Ok(Some(action))
+  } else {
+    Err(ParserError::msg("\"use:\" must be followed by the name of a variable holding the action", prop.node))
+  }
+}
+
+/// Folded constants are merged into the text node around them.
+fn walked_kind(kind: &str) -> &str {
+  if kind == CONSTANT_KIND { "jsx_text" } else { kind }
+}
+
+/// Value of a `$switch`, `$case`, `$if` or `$elseif`, string attributes become JS strings and JSX
+/// in expressions is compiled like in any other prop.
+fn directive_value<'v>(
+  prop: &Prop<'v>,
+  fallback: &'v str,
+  templates: &[JsxTemplate],
+  state: &mut GlobalState,
+) -> Result<Cow<'v, str>, ParserError> {
+  match prop.value {
+    None => Ok(Cow::Borrowed(fallback)),
+    Some(_) if prop.kind == "string_fragment" => Ok(static_prop_value(prop)),
+    Some(value) => replace_jsx(prop.node, templates, value, state),
+  }
+}
+
+/// Selector for an `$if` chain, picks the first branch whose condition holds.
+fn if_chain_selector(
+  cond: &Prop,
+  branches: &[&JsxTemplate],
+  templates: &[JsxTemplate],
+  state: &mut GlobalState,
+) -> Result<String, ParserError> {
+  let mut selector = format!("() => ({}) ? 0 : ", directive_value(cond, "true", templates, state)?);
+  for (i, branch) in branches.iter().enumerate() {
+    match &branch.branch {
+      Some(Branch::ElseIf(prop)) => {
+        write!(selector, "({}) ? {} : ", directive_value(prop, "true", templates, state)?, i + 1)?
+      }
+      _ => {
+        write!(selector, "{}", i + 1)?;
+        return Ok(selector);
+      }
+    }
+  }
+  write!(selector, "-1")?;
+  Ok(selector)
+}
+
+/// Renders whichever of `branches` the selector picks in place of the `anchor` comment.
+fn generate_branch_render(
+  anchor: &str,
+  selector: &str,
+  branches: &[&JsxTemplate],
+  templates: &[JsxTemplate],
+  state: &mut GlobalState,
+) -> Result<String, ParserError> {
+  let is_component_child = state.is_component_child;
+
+  let mut creates = Vec::with_capacity(branches.len());
+  for branch in branches {
+    // Components are called as is, elements are created from their own template
+    state.is_component_child = branch.is_component();
+    state.is_branch = branch.is_component();
+    let parts = branch.parts(templates, state)?;
+    creates.push(create_fn(&parts));
+  }
+  state.is_component_child = is_component_child;
+
+  state.imports.insert("branchRender");
+  Ok(format!("{VAR_PREF}branchRender({anchor}, {selector}, [{}])", creates.join(", ")))
+}
+
+/// `parts.create_fn` as a function instead of an immediately invoked one.
+fn create_fn(parts: &TemplateParts) -> String {
+  if parts.create_fn.trim_end().ends_with(']') {
+    format!("() => ({})", parts.create_fn.trim_end())
+  } else {
+    parts.create_fn[..parts.create_fn.len() - 2].to_string()
+  }
+}
@@ -1,6 +1,8 @@
 #[cfg(test)]
-mod tests {
-  use crate::jsx_parser::{utils::merge_jsx_text, JsParser, JsxTemplate, ParserError, Q_JSX_TEMPLATE};
+pub(super) mod tests {
+  use crate::jsx_parser::{
+    utils::merge_jsx_text, GenOptions, GlobalState, JsParser, JsxTemplate, OutputStyle, ParserError, Q_JSX_TEMPLATE,
+  };
 
   macro_rules! parse_templates {
     (let $name: ident = $src: expr) => {
@@ -13,13 +15,35 @@ mod tests {
         .expect("Tree root node should parse");
 
       let $name = matches
-        .enumerate()
-        .map(|(i, m)| JsxTemplate::parse(i, m.captures, source))
+        .map(|m| JsxTemplate::parse(m.captures, source))
         .collect::<Result<Box<_>, ParserError>>()
         .expect("Templates should parse");
     };
   }
 
+  pub(in crate::jsx_parser) fn transform(source: &[u8]) -> String {
+    transform_with(source, GenOptions::default())
+  }
+
+  fn transform_with(source: &[u8], options: GenOptions) -> String {
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let mut state = GlobalState::new(None, options);
+    let mut out = Vec::new();
+    parser
+      .parse_jsx_templates(source, &mut state, &mut out)
+      .expect("Templates should generate");
+    String::from_utf8(out).expect("Output should be UTF-8")
+  }
+
+  fn transform_err(source: &[u8]) -> &'static str {
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let mut state = GlobalState::new(None, GenOptions::default());
+    match parser.parse_jsx_templates(source, &mut state, &mut Vec::new()) {
+      Err(ParserError::ParseMsg { msg, .. }) => msg,
+      res => panic!("Expected a parse error, got {res:?}"),
+    }
+  }
+
   #[test]
   fn test_basic_text_escaping() {
     parse_templates!(
@@ -30,11 +54,11 @@ mod tests {
     assert_eq!(templates[0].children.len(), 1);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Basic text without special characters\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Basic text without special characters");
   }
 
@@ -48,11 +72,11 @@ mod tests {
     assert_eq!(templates[0].children.len(), 1);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\" Text between spaces \"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, " Text between spaces ");
   }
 
@@ -66,11 +90,11 @@ mod tests {
     assert_eq!(templates[0].children.len(), 8);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Text with \\xA0 entities & symbols < >\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Text with &nbsp; entities &amp; symbols &lt; &gt;");
   }
 
@@ -89,11 +113,11 @@ mod tests {
     assert_eq!(templates[0].children.len(), 1);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Text with multiple spaces\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Text with multiple spaces");
   }
 
@@ -112,27 +136,27 @@ mod tests {
     assert_eq!(templates[1].children.len(), 3);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Text before \"");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\" text after\"");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"inner text\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Text before ");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, " text after");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "inner text");
   }
 
@@ -152,27 +176,27 @@ mod tests {
     assert_eq!(templates[1].children.len(), 3);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"inner text\"");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Text before\"");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"text after\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "inner text");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Text before");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "text after");
   }
 
@@ -186,11 +210,11 @@ mod tests {
     assert_eq!(templates[0].children.len(), 1);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\" \"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, " ");
   }
 
@@ -211,27 +235,27 @@ mod tests {
     assert_eq!(templates[1].children.len(), 3);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Outer text \"");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\" Inner text \"");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"More outer text\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Outer text ");
 
     idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, " Inner text ");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[1].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[1].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "More outer text");
   }
 
@@ -245,14 +269,14 @@ mod tests {
     assert_eq!(templates[0].children.len(), 5);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(
       text,
       "\" Unicode text with emojis 😊 and non-ASCII ￼ 𝔸 characters: äöüß \""
     );
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(
       text,
       " Unicode text with emojis 😊 and non-ASCII &#xFFFC; &#120120; characters: äöüß "
@@ -286,35 +310,917 @@ mod tests {
     assert_eq!(templates[3].children.len(), 11);
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"lol\"");
 
     let mut idx = 1;
-    let text = merge_jsx_text(&templates[2].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[2].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"ok\"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[3].children, &mut idx, true).expect("Text should parse");
+    let text = merge_jsx_text(&templates[3].children, &mut idx, true, false).expect("Text should parse");
     assert_eq!(text, "\"Some text \"");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[3].children, &mut idx, true).expect("Text should parse");
-    assert_eq!(text, "\" ok \"");
+    let text = merge_jsx_text(&templates[3].children, &mut idx, true, false).expect("Text should parse");
+    // `{15}` is folded into the text
+    assert_eq!(text, "\" ok 15 \"");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[0].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[0].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "lol");
 
     let mut idx = 1;
-    let text = merge_jsx_text(&templates[2].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[2].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "ok");
 
     let mut idx = 0;
-    let text = merge_jsx_text(&templates[3].children, &mut idx, false).expect("Text should parse");
+    let text = merge_jsx_text(&templates[3].children, &mut idx, false, false).expect("Text should parse");
     assert_eq!(text, "Some text ");
 
     idx += 1;
-    let text = merge_jsx_text(&templates[3].children, &mut idx, false).expect("Text should parse");
-    assert_eq!(text, " ok ");
+    let text = merge_jsx_text(&templates[3].children, &mut idx, false, false).expect("Text should parse");
+    assert_eq!(text, " ok 15 ");
+  }
+
+  #[test]
+  fn test_element_spread_attributes() {
+    let out = transform(br#"<div title="a" {...attrs} class:b id="z" $data-x={d()} on:click={f}></div>"#);
+
+    assert!(out.contains("_jsx$template(`<div title=\"a\"></div>`)"), "{out}");
+    assert!(
+      out.contains(r#"_jsx$spreadAttributes(_jsx$el0, () => (attrs), ["class:b", "id", "data-x", "on:click"]);"#),
+      "{out}"
+    );
+    assert!(
+      out.contains(r#"_jsx$spreadAttributes(_jsx$el0, {"class:b": true, "id": "z"});"#),
+      "{out}"
+    );
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "data-x", () => d());"#), "{out}");
+  }
+
+  #[test]
+  fn test_spread_attributes_in_source_order() {
+    let out = transform(br#"<div $title={t()} class:a={a()} {...attrs} on:click|stop={f} $id={i()}></div>"#);
+
+    // Tracked props before the spread go through it so its keys win
+    assert!(
+      out.contains(r#"_jsx$spreadAttributes(_jsx$el0, () => ({"$title": t(), "class:a": a(), ...(attrs)}), ["on:click", "id"]);"#),
+      "{out}"
+    );
+    assert!(!out.contains("trackClass") && !out.contains(r#""title", () => t()"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "id", () => i());"#), "{out}");
+  }
+
+  #[test]
+  fn test_component_spread_props() {
+    let out = transform(br#"<Button a="1" {...props()} b={y()} />"#);
+
+    assert!(
+      out.contains(r#"Button(_jsx$mergeProps({a: "1", }, () => (props()), {get b() { return y() }, }))"#),
+      "{out}"
+    );
+  }
+
+  #[test]
+  fn test_member_expression_component_tags() {
+    let out = transform(br#"<div><ui.Button a="1" /><icons.close /><this.Row>r</this.Row><icon $component /></div>"#);
+
+    assert!(out.contains("_jsx$template(`<div><!><!><!><!></div>`)"), "{out}");
+    assert!(out.contains(r#"ui.Button({a: "1", })"#), "{out}");
+    assert!(out.contains("icons.close({})"), "{out}");
+    assert!(out.contains(r#"this.Row({}, {default: () => ["r"]})"#), "{out}");
+    assert!(out.contains("icon({})"), "{out}");
+  }
+
+  #[test]
+  fn test_template_escaping() {
+    let out = transform(br#"<p title='say "${hi}" \ `now`'>a ` b ${c} \ d</p>"#);
+
+    assert!(
+      out.contains(r#"_jsx$template(`<p title="say &quot;\${hi}&quot; \\ \`now\`">a \` b $<!> \\ d</p>`)"#),
+      "{out}"
+    );
+  }
+
+  #[test]
+  fn test_component_string_prop_escaping() {
+    let out = transform(br#"<Foo a='"\' />"#);
+
+    assert!(out.contains(r#"Foo({a: "\"\\", })"#), "{out}");
+  }
+
+  #[test]
+  fn test_detached_svg_templates() {
+    let out = transform(
+      br#"
+      const dot = <circle cx="1" />;
+      const icon = <svg><g>{items.map(() => <rect />)}</g><foreignObject><div /></foreignObject></svg>;
+      const frac = <mfrac><mi>x</mi></mfrac>;
+    "#,
+    );
+
+    assert!(out.contains(r#"_jsx$template(`<circle cx="1"></circle>`, "svg")"#), "{out}");
+    assert!(out.contains(r#"_jsx$template(`<rect></rect>`, "svg")"#), "{out}");
+    assert!(
+      out.contains("_jsx$template(`<svg><g><!></g><foreignObject><div></div></foreignObject></svg>`);"),
+      "{out}"
+    );
+    assert!(out.contains(r#"_jsx$template(`<mfrac><mi>x</mi></mfrac>`, "math")"#), "{out}");
+  }
+
+  #[test]
+  fn test_namespaced_attributes() {
+    let out = transform(br#"<use xlink:title="a" xlink:href={url} $xml:lang={lang()} />"#);
+
+    assert!(out.contains(r#"_jsx$template(`<use xlink:title="a"></use>`, "svg")"#), "{out}");
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "xlink:href", url);"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "xml:lang", () => lang());"#), "{out}");
+  }
+
+  #[test]
+  fn test_preformatted_template_keeps_leading_newline() {
+    let out = transform(b"<pre>\n  a   b\n</pre>");
+
+    assert!(out.contains("_jsx$template(`<pre>\n\n  a   b\n</pre>`)"), "{out}");
+  }
+
+  #[test]
+  fn test_prop_and_attr_directives() {
+    let out = transform(
+      br#"<input prop:indeterminate prop:title="a" prop:volume={0.5} prop:value={text()} attr:value="b" attr:max={max()} />"#,
+    );
+
+    assert!(out.contains(r#"_jsx$template(`<input value="b"/>`)"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0["indeterminate"] = true;"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0["title"] = "a";"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0["volume"] = 0.5;"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackProperty(_jsx$el0, "value", () => text());"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "max", () => max(), false);"#), "{out}");
+  }
+
+  #[test]
+  fn test_custom_element_props() {
+    let source = br#"<x-chart title="a" data={points} $range={range()} />"#;
+
+    let out = transform(source);
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "data", points);"#), "{out}");
+
+    let out = transform_with(source, GenOptions { custom_element_props: true, ..Default::default() });
+    assert!(out.contains(r#"_jsx$template(`<x-chart title="a"></x-chart>`)"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0["data"] = points;"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackProperty(_jsx$el0, "range", () => range());"#), "{out}");
+  }
+
+  #[test]
+  fn test_event_modifiers() {
+    let out = transform(
+      br#"<form on:submit|prevent|once={save} on:scroll|passive={scroll} g:onkeydown|stop={key}>{a || b}</form>"#,
+    );
+
+    assert!(
+      out.contains(
+        r#"_jsx$addLocalEvent(_jsx$el0, "submit", [((_jsx$fn) => function (_jsx$e) { _jsx$e.preventDefault(); return _jsx$fn.call(this, _jsx$e); })(save), { once: true }]);"#
+      ),
+      "{out}"
+    );
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el0, "scroll", [scroll, { passive: true }]);"#), "{out}");
+    assert!(out.contains(r#"_jsx$createGlobalEvent("keydown")"#), "{out}");
+    assert!(
+      out.contains(
+        r#"_jsx$addGlobalEvent(window._jsx$global_event_keydown, _jsx$el0, ((_jsx$fn) => function (_jsx$e) { _jsx$e.stopPropagation(); return _jsx$fn.call(this, _jsx$e); })(key));"#
+      ),
+      "{out}"
+    );
+    assert!(out.contains("a || b"), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_event_modifiers() {
+    assert!(transform_err(br#"<a on:click|nope={f} />"#).starts_with("Unknown event modifier"));
+    assert!(transform_err(br#"<a on:wheel|passive|prevent={f} />"#).starts_with("Passive listeners"));
+    assert!(transform_err(br#"<a g:onscroll|capture={f} />"#).starts_with("g:on events"));
+    assert!(transform_err(br#"<Foo on:click|prevent={f} />"#).starts_with("Event modifiers"));
+  }
+
+  #[test]
+  fn test_delegated_events() {
+    let source = br#"<ul on:click={select} on:scroll={scroll} on:keydown|once={key} on:input={[edit, {}]}>
+      <li on:click|stop={remove} on:mouseenter={hover} />
+      <a on:click|prevent={open} on:touchstart={touch} />
+    </ul>"#;
+    let out = transform_with(source, GenOptions { delegate_events: true, ..Default::default() });
+
+    assert!(out.contains(r#"_jsx$delegateEvent("click");"#), "{out}");
+    assert!(out.contains("_jsx$el0.$$click = select;"), "{out}");
+    assert!(out.contains("_jsx$el1.$$click = ((_jsx$fn) =>"), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el0, "scroll", scroll);"#), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el0, "keydown", [key, { once: true }]);"#), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el0, "input", [edit, {}]);"#), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el1, "mouseenter", hover);"#), "{out}");
+    assert!(!out.contains(r#"_jsx$delegateEvent("scroll")"#), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el2, "click", ((_jsx$fn) =>"#), "{out}");
+    assert!(out.contains(r#"_jsx$addLocalEvent(_jsx$el2, "touchstart", touch);"#), "{out}");
+
+    // Emitted in a stable order so builds are reproducible
+    let out = transform_with(
+      br#"<p on:keyup={a} on:click={b} on:input={c} on:dblclick={d} />"#,
+      GenOptions { delegate_events: true, ..Default::default() },
+    );
+    assert!(
+      out.contains(
+        "_jsx$delegateEvent(\"click\");\n_jsx$delegateEvent(\"dblclick\");\n_jsx$delegateEvent(\"input\");\n_jsx$delegateEvent(\"keyup\");"
+      ),
+      "{out}"
+    );
+
+    let out = transform(source);
+    assert!(!out.contains("delegateEvent"), "{out}");
+  }
+
+  #[test]
+  fn test_bind_directives() {
+    let out = transform(
+      br#"<form bind:this={form.el}>
+        <input bind:value={name} />
+        <input type="checkbox" bind:checked={[done, setDone]} />
+        <input type="radio" value="a" bind:group={state.picked} />
+        <select bind:value={state["size"]} bind:this={selectRef} />
+      </form>"#,
+    );
+
+    assert!(out.contains("form.el = _jsx$el0;"), "{out}");
+    assert!(out.contains(r#"_jsx$bindProperty(_jsx$el1, "value", "input", name);"#), "{out}");
+    assert!(out.contains(r#"_jsx$bindProperty(_jsx$el2, "checked", "change", [done, setDone]);"#), "{out}");
+    assert!(
+      out.contains("_jsx$bindGroup(_jsx$el3, [() => state.picked, (_jsx$v) => state.picked = _jsx$v]);"),
+      "{out}"
+    );
+    assert!(
+      out.contains(r#"_jsx$bindProperty(_jsx$el4, "value", "change", [() => state["size"], (_jsx$v) => state["size"] = _jsx$v]);"#),
+      "{out}"
+    );
+    assert!(out.contains("selectRef = _jsx$el4;"), "{out}");
+  }
+
+  #[test]
+  fn test_bind_identifiers() {
+    let out = transform(
+      br#"let el;
+      const name = ref("");
+      const size = ref("m");
+      let dialog;
+      const p = <div bind:this={el}><input bind:value={name} /><select bind:value={size} /><dialog bind:this={dialog} /></div>;"#,
+    );
+
+    // `bind:this` assigns to a variable, other bindings take the `ref()` tuple it holds
+    assert!(out.contains("el = _jsx$el0;"), "{out}");
+    assert!(out.contains(r#"_jsx$bindProperty(_jsx$el1, "value", "input", name);"#), "{out}");
+    assert!(out.contains(r#"_jsx$bindProperty(_jsx$el2, "value", "change", size);"#), "{out}");
+    assert!(out.contains("dialog = _jsx$el3;"), "{out}");
+
+    let out = transform(br#"<canvas bind:this={[getCanvas, setCanvas]} />"#);
+    assert!(out.contains("([getCanvas, setCanvas])[1](_jsx$el0);"), "{out}");
+  }
+
+  #[test]
+  fn test_preserve_whitespace_values() {
+    let out = transform(br#"<p $preserveWhitespace={false}> a  b </p>"#);
+    assert!(out.contains("_jsx$template(`<p> a b </p>`)"), "{out}");
+
+    for src in [&br#"<p $preserveWhitespace={keep}> a </p>"#[..], br#"<p $preserveWhitespace="true"> a </p>"#] {
+      assert!(transform_err(src).starts_with(r#""$preserveWhitespace" must be used"#));
+    }
+  }
+
+  #[test]
+  fn test_invalid_bind_directives() {
+    assert!(transform_err(br#"<input bind:value="a" />"#).starts_with("bind: expects"));
+    assert!(transform_err(br#"<input bind:value={get()} />"#).starts_with("bind: expects"));
+    assert!(transform_err(br#"<input bind:files={files} />"#).starts_with("Unknown bind:"));
+  }
+
+  #[test]
+  fn test_use_actions() {
+    let out = transform(br#"<input use:autofocus use:tooltip={text()} use:clickOutside={close} use:mask="99-99" />"#);
+
+    assert!(out.contains("_jsx$template(`<input/>`)"), "{out}");
+    assert!(out.contains("_jsx$useAction(_jsx$el0, autofocus);"), "{out}");
+    assert!(out.contains("_jsx$useAction(_jsx$el0, tooltip, () => text());"), "{out}");
+    assert!(out.contains("_jsx$useAction(_jsx$el0, clickOutside, () => close);"), "{out}");
+    assert!(out.contains(r#"_jsx$useAction(_jsx$el0, mask, () => "99-99");"#), "{out}");
+    // Re-mounting restarts the actions, the observer dispatches the events they listen to
+    assert_eq!(out.matches("_jsx$observeTree(_jsx$mutObserver, _jsx$el0, false);").count(), 1, "{out}");
+    assert!(out.contains("_jsx$createMutationObserver()"), "{out}");
+
+    let out = transform(br#"<input use:autofocus on:mount={f} />"#);
+    assert_eq!(out.matches("_jsx$observeTree(").count(), 1, "{out}");
+
+    for src in [&br#"<div use:click-outside={close} />"#[..], br#"<div use:click-outside />"#, br#"<div use:2d />"#] {
+      assert!(transform_err(src).starts_with(r#""use:" must be followed"#));
+    }
+  }
+
+  #[test]
+  fn test_if_else_chains() {
+    let out = transform(
+      br#"<div>
+        <p $if={x() > 1}>big</p>
+        <p $elseif={x() === 1}>one</p>
+        <p $else>none</p>
+        <span $if={y()} />
+      </div>"#,
+    );
+
+    assert!(out.contains("_jsx$template(`<div><!><!></div>`)"), "{out}");
+    assert!(out.contains("_jsx$branchRender(_jsx$el1, () => (x() > 1) ? 0 : (x() === 1) ? 1 : 2, [(() => {"), "{out}");
+    assert!(out.contains("_jsx$template(`<p>none</p>`)"), "{out}");
+    assert!(out.contains("const _jsx$el2 = _jsx$el1.nextSibling;"), "{out}");
+    assert!(out.contains("_jsx$conditionalRender(_jsx$el2, "), "{out}");
+    assert_eq!(out.matches("branchRender(").count(), 1, "{out}");
+  }
+
+  #[test]
+  fn test_if_chain_in_component_children() {
+    let out = transform(br#"<Foo><p $if={a}>a</p> <Bar $elseif={b} /></Foo>"#);
+
+    assert!(
+      out.contains(r#"default: () => [(() => _jsx$branchRender(document.createComment(""), () => (a) ? 0 : (b) ? 1 : -1, [(() => {"#),
+      "{out}"
+    );
+    assert!(out.contains("const _jsx$el0 = Bar({});"), "{out}");
+  }
+
+  #[test]
+  fn test_switch_case() {
+    let out = transform(
+      br#"<ul $switch={tab()}>
+        <li $case={"a"}>A</li>
+        <li $default>?</li>
+        <li $case={"b"}>B</li>
+      </ul>"#,
+    );
+
+    assert!(out.contains("_jsx$template(`<ul><!></ul>`)"), "{out}");
+    assert!(
+      out.contains(r#"_jsx$branchRender(_jsx$el1, () => { const _jsx$v = tab(); return _jsx$v === ("a") ? 0 : _jsx$v === ("b") ? 2 : 1; }, ["#),
+      "{out}"
+    );
+    assert!(out.contains("_jsx$template(`<li>?</li>`)"), "{out}");
+  }
+
+  #[test]
+  fn test_branch_values() {
+    let out = transform(br#"<ul $switch={x()}><li $case="a">A</li><li $case={"b"}>B</li><li $case={<i />}>C</li></ul>"#);
+
+    assert!(
+      out.contains(r#"() => { const _jsx$v = x(); return _jsx$v === ("a") ? 0 : _jsx$v === ("b") ? 1 : _jsx$v === ("#),
+      "{out}"
+    );
+    assert!(!out.contains("(a)") && !out.contains("<i />"), "{out}");
+
+    let out = transform(br#"<div><p $if={mode() === "x"}>x</p><p $elseif="yes">y</p><p $else>z</p></div>"#);
+
+    assert!(out.contains(r#"() => (mode() === "x") ? 0 : ("yes") ? 1 : 2"#), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_branches() {
+    assert!(transform_err(br#"<div><p $else /></div>"#).starts_with(r#""$elseif" and "$else" must follow"#));
+    assert!(transform_err(br#"<div><p $if={a} />text<p $else /></div>"#).starts_with(r#""$elseif" and "$else""#));
+    assert!(transform_err(br#"<div><p $case={1} /></div>"#).starts_with(r#""$case" and "$default""#));
+    assert!(transform_err(br#"<ul $switch={a}><li $case={1} /><li /></ul>"#).starts_with(r#"Children of "$switch""#));
+    assert!(transform_err(br#"<ul $switch={a}>text</ul>"#).starts_with(r#"Children of "$switch""#));
+    assert!(transform_err(br#"<ul $switch={a}><li $default /><li $default /></ul>"#).starts_with(r#""$switch" can only"#));
+    assert!(transform_err(br#"<div><p $if={a} $else /></div>"#).starts_with("Only one of"));
+    assert!(transform_err(br#"<div><p $else={a} /></div>"#).starts_with(r#""$else" and "$default" must not"#));
+    assert!(transform_err(br#"const p = <p $else />;"#).starts_with(r#""$elseif" and "$else" must follow"#));
+    assert!(transform_err(br#"const p = <Foo $elseif={a} />;"#).starts_with(r#""$elseif" and "$else" must follow"#));
+  }
+
+  #[test]
+  fn test_show_directive() {
+    let out = transform(br#"<div><input $show={open()} /><p $show={visible} $transition:fade>Hi</p><b $show={true} /></div>"#);
+
+    assert!(out.contains("_jsx$template(`<div><input/><p>Hi</p><b></b></div>`)"), "{out}");
+    assert!(out.contains("_jsx$trackShow(_jsx$el1, () => open());"), "{out}");
+    assert!(out.contains(r#"_jsx$trackShow(_jsx$el2, () => visible, "fade");"#), "{out}");
+    assert!(out.contains("_jsx$trackShow(_jsx$el4, true);"), "{out}");
+    assert!(!out.contains("createTransition"), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_show_directive() {
+    assert!(transform_err(br#"<p $show />"#).starts_with(r#""$show" must have a value"#));
+    assert!(transform_err(br#"<p $show={a} $transition={b} />"#).starts_with(r#""$transition" must not"#));
+  }
+
+  #[test]
+  fn test_key_directive() {
+    let out = transform(br#"<div><form $key={user().id}><input /></form></div>"#);
+
+    assert!(out.contains("_jsx$template(`<div><!></div>`)"), "{out}");
+    assert!(out.contains("_jsx$template(`<form><input/></form>`)"), "{out}");
+    assert!(out.contains("_jsx$keyedRender(_jsx$el1, (() => {"), "{out}");
+    assert!(out.contains("}), () => (user().id));"), "{out}");
+
+    let out = transform(br#"<Foo><p $key={n}>{n}</p></Foo>"#);
+    assert!(out.contains(r#"_jsx$keyedRender(document.createComment(""), (() => {"#), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_key_directive() {
+    assert!(transform_err(br#"<p $key />"#).starts_with(r#""$key" must have a value"#));
+    assert!(transform_err(br#"<p $key={a} $if={b} />"#).starts_with(r#""$key" can't be combined"#));
+  }
+
+  #[test]
+  fn test_component_directives() {
+    let out = transform(
+      br#"<div>
+        <Modal $if={open()} title="x" />
+        <Card $ref={card} $show={visible()} $transition:fade />
+        <Item $key={id()} />
+        <Tip $transition:fade={hover()} />
+      </div>"#,
+    );
+
+    assert!(out.contains("_jsx$template(`<div><!><!><!><!></div>`)"), "{out}");
+    assert!(out.contains("_jsx$conditionalRender(_jsx$el1, () => {\nconst _jsx$node = Modal({title: \"x\", });\nreturn _jsx$node;\n}, () => open());"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el2, (() => {"), "{out}");
+    assert!(out.contains(r#"_jsx$trackShow(_jsx$node, () => visible(), "fade");"#), "{out}");
+    assert!(out.contains("card = _jsx$node;"), "{out}");
+    assert!(out.contains(r#"const _jsx$node = _jsx$singleElement(Card({}), "Card");"#), "{out}");
+    assert!(out.contains(r#"const _jsx$node = _jsx$singleElement(Tip({}), "Tip");"#), "{out}");
+    assert!(out.contains("const _jsx$node = Item({});"), "{out}");
+    assert!(out.contains("_jsx$keyedRender(_jsx$el3, () => {"), "{out}");
+    assert!(out.contains(r#"}, () => hover(), "fade");"#), "{out}");
+
+    let out = transform(br#"const m = <Modal $if={open()} $refFn={setModal} />;"#);
+    assert!(out.contains(r#"const _jsx$el0 = _jsx$conditionalRender(document.createComment(""), () => {"#), "{out}");
+    assert!(out.contains("setModal(_jsx$node);"), "{out}");
+  }
+
+  #[test]
+  fn test_component_if_chain_is_not_wrapped_twice() {
+    let out = transform(br#"<div><A $if={x} /><B $else /></div>"#);
+
+    assert!(out.contains("_jsx$branchRender(_jsx$el1, () => (x) ? 0 : 1, [(() => {"), "{out}");
+    assert!(!out.contains("conditionalRender"), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_component_directives() {
+    assert!(transform_err(br#"<Foo use:tooltip={a} />"#).starts_with(r#""use:", "bind:""#));
+    assert!(transform_err(br#"<Foo bind:value={a} />"#).starts_with(r#""use:", "bind:""#));
+    assert!(transform_err(br#"<Foo $switch={a}><p $case={1} /></Foo>"#).starts_with(r#""$switch" only works"#));
+    assert!(transform_err(br#"<Modal $transition:fade />"#).starts_with(r#""$transition" without a value"#));
+    assert!(transform_err(br#"<Modal $if={a} $transition:fade={b} />"#).starts_with(r#""$transition" must not have a value"#));
+  }
+
+  #[test]
+  fn test_if_with_transition() {
+    let out = transform(br#"<div><Modal $if={open()} $transition:fade /><p $if={tip} $transition>Tip</p></div>"#);
+
+    assert!(out.contains(r#"_jsx$createTransition(_jsx$el1, () => {"#), "{out}");
+    assert!(out.contains(r#"}, () => open(), "fade");"#), "{out}");
+    assert!(out.contains(r#"() => tip, "jsx");"#), "{out}");
+    assert!(!out.contains("conditionalRender"), "{out}");
+  }
+
+  #[test]
+  fn test_transitions_in_if_chains() {
+    assert!(transform_err(br#"<div><p $if={a} /><p $else $transition:fade /></div>"#).starts_with(r#""$transition" can't be used on "$elseif""#));
+    assert!(transform_err(br#"<div><p $if={a} $transition:fade /><p $else /></div>"#).starts_with(r#""$transition" can't be used on an "$if""#));
+    assert!(transform_err(br#"<Foo><p $if={a} $transition:fade /><p $else /></Foo>"#).starts_with(r#""$transition" can't be used on an "$if""#));
+  }
+
+  #[test]
+  fn test_slots_are_passed_per_call() {
+    let out = transform(
+      br#"function TreeNode(props) {
+        return <li>
+          {props.node.name}
+          <ul>{props.node.children.map((child) => <TreeNode node={child}><b>{child.name}</b></TreeNode>)}</ul>
+          <slot />
+        </li>;
+      }
+      const tree = <TreeNode node={root}><i>root</i></TreeNode>;"#,
+    );
+
+    assert!(!out.contains("$$slots"), "{out}");
+    assert!(out.contains("TreeNode({get node() { return child }, }, {default: () => [(() => {"), "{out}");
+    assert!(out.contains("TreeNode({get node() { return root }, }, {default: () => [(() => {"), "{out}");
+    assert!(out.contains("function TreeNode(props, _jsx$slots) {"), "{out}");
+    assert_eq!(out.matches(r#"_jsx$slots?.["default"]?.()"#).count(), 1, "{out}");
+  }
+
+  #[test]
+  fn test_slots_in_arrow_components() {
+    let out = transform(br#"const Card = () => <div><slot name="title" /></div>;"#);
+    assert!(out.contains("const Card = (_jsx$props, _jsx$slots) => "), "{out}");
+    assert!(out.contains(r#"_jsx$insertChild(_jsx$el1, _jsx$slots?.["title"]?.());"#), "{out}");
+
+    let out = transform(br#"const Card = props => <div>{props.title}<slot /></div>;"#);
+    assert!(out.contains("const Card = (props, _jsx$slots) => "), "{out}");
+
+    let out = transform(br#"const Card = ({ title } = {}) => <div>{title}<slot /></div>;"#);
+    assert!(out.contains("const Card = (_jsx$props, _jsx$slots) => "), "{out}");
+
+    let out = transform(br#"export const List = (props, children) => <ul>{props.items.map(() => <li><slot /></li>)}</ul>;"#);
+    assert!(out.contains("const List = (props, children) => "), "{out}");
+    assert!(out.contains(r#"children?.["default"]?.()"#), "{out}");
+
+    let out = transform(br#"const Card = function () { return <div><slot /><slot name="footer" /></div>; };"#);
+    assert!(out.contains("const Card = function (_jsx$props, _jsx$slots) {"), "{out}");
+    assert!(out.contains(r#"_jsx$slots?.["footer"]?.()"#), "{out}");
+  }
+
+  #[test]
+  fn test_slot_outside_component() {
+    assert_eq!(transform_err(br#"const a = <div><slot /></div>;"#), "<slot> must be used inside a component function");
+    assert_eq!(transform_err(br#"const a = <div>{items.map(() => <slot />)}</div>;"#), "<slot> must be used inside a component function");
+    assert_eq!(
+      transform_err(br#"function render(items) { return items.map(() => <li><slot /></li>); }"#),
+      "<slot> must be used inside a component function"
+    );
+    assert!(transform_err(br#"function Card(props, { header }) { return <slot />; }"#).starts_with("<slot> needs"));
+  }
+
+  #[test]
+  fn test_slot_fallback() {
+    let out = transform(
+      br#"function Card(props) {
+        return <div><slot name="footer">Default {props.x} <b class:y={on()}>{n()}</b></slot><slot>   </slot></div>;
+      }"#,
+    );
+
+    assert!(out.contains("_jsx$template(`<div><!><slot> </slot></div>`)"), "{out}");
+    assert!(out.contains(r#"_jsx$insertChild(_jsx$el1, _jsx$slots?.["footer"]?.() ?? ["Default ", () => props.x, " ", (() => {"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackClass(_jsx$el0, "y", () => on());"#), "{out}");
+    assert!(out.contains(r#"_jsx$insertChild(_jsx$el2, _jsx$slots?.["default"]?.());"#), "{out}");
+  }
+
+  #[test]
+  fn test_scoped_slots() {
+    let out = transform(
+      br#"function Table(props) {
+        return <table>{props.rows.map((row, i) => <tr><slot name="row" item={row} index={i} label="x" striped /></tr>)}<slot count={props.rows.length} /></table>;
+      }
+      const t = <Table rows={rows()} let:count>
+        <td slot="row" let:item let:index={i}>{i()}: {item().name}</td>
+        Total {count()}
+      </Table>;"#,
+    );
+
+    assert!(out.contains("_jsx$template(`<tr><slot></slot></tr>`)"), "{out}");
+    assert!(out.contains("props.rows.map((row, i) => (() => {"), "{out}");
+    assert!(out.contains(r#"_jsx$slots?.["row"]?.({get "item"() { return row }, get "index"() { return i }, "label": "x", "striped": true, })"#), "{out}");
+    assert!(out.contains(r#"_jsx$slots?.["default"]?.({get "count"() { return props.rows.length }, })"#), "{out}");
+    assert!(out.contains("Table({get rows() { return rows() }, }, {row: (_jsx$slotProps) => {\nconst item = () => _jsx$slotProps[\"item\"];\nconst i = () => _jsx$slotProps[\"index\"];\nreturn (() => {"), "{out}");
+    assert!(out.contains("default: (_jsx$slotProps) => {\nconst count = () => _jsx$slotProps[\"count\"];\nreturn [\"Total \",() => count()];\n}}"), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_scoped_slots() {
+    assert!(transform_err(br#"<div let:item>{item()}</div>"#).starts_with(r#""let:" only works"#));
+    assert!(transform_err(br#"<Foo><b slot="a" let:item={x.y} /></Foo>"#).starts_with(r#""let:" can only be renamed"#));
+    assert_eq!(transform_err(br#"function Foo(props) { return <slot {...props} />; }"#), "Spread props aren't supported on <slot>");
+  }
+
+  #[test]
+  fn test_destructured_props() {
+    let out = transform(
+      br#"function Card({ title, count = 0, user: { name }, "data-x": dx, ...rest }) {
+        const label = title + count;
+        const other = (title) => title;
+        return <section {...rest} data-x={dx}><title>{title}</title><h1>{name} {count}</h1>{other({ title })}</section>;
+      }
+      const Row = ({ item } = {}) => <li>{item.name}</li>;
+      function helper({ a }) { return a; }"#,
+    );
+
+    assert!(out.contains("function Card(_jsx$props) { const _jsx$props_count = _jsx$lazyDefault(() => (0)); "), "{out}");
+    assert!(out.contains("const label = _jsx$props.title + (_jsx$props.count === undefined ? _jsx$props_count() : _jsx$props.count);"), "{out}");
+    assert!(out.contains("const other = (title) => title;"), "{out}");
+    assert!(out.contains("<section><title><!></title>"), "{out}");
+    assert!(out.contains(r#"_jsx$spreadAttributes(_jsx$el0, () => (_jsx$omitProps(_jsx$props, ["title", "count", "user", "data-x", ])), ["data-x"]);"#), "{out}");
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "data-x", _jsx$props["data-x"]);"#), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el2, () => _jsx$props.title);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el4, () => _jsx$props.user.name);"), "{out}");
+    assert!(out.contains("other({ title: _jsx$props.title })"), "{out}");
+    assert!(out.contains("const Row = (_jsx$props) => (() => {"), "{out}");
+    assert!(out.contains("() => _jsx$props.item.name"), "{out}");
+    assert!(out.contains("function helper({ a }) { return a; }"), "{out}");
+  }
+
+  #[test]
+  fn test_destructured_props_defaults() {
+    let out = transform(
+      br#"function Card({ a, b = a + 1, items = [a], style: { color } = { color: a } }) {
+        return <p title={b}>{items.length} {color}</p>;
+      }
+      const Row = ({ icon = <i /> }) => <li>{icon}</li>;"#,
+    );
+
+    // Evaluated once per call, reading the sibling props like the body does
+    assert!(out.contains("const _jsx$props_b = _jsx$lazyDefault(() => (_jsx$props.a + 1));"), "{out}");
+    assert!(out.contains("const _jsx$props_items = _jsx$lazyDefault(() => ([_jsx$props.a]));"), "{out}");
+    assert!(out.contains("const _jsx$props_2 = _jsx$lazyDefault(() => ({ color: _jsx$props.a }));"), "{out}");
+    assert!(out.contains("(_jsx$props.b === undefined ? _jsx$props_b() : _jsx$props.b)"), "{out}");
+    assert!(out.contains("(_jsx$props.items === undefined ? _jsx$props_items() : _jsx$props.items).length"), "{out}");
+    assert!(
+      out.contains("(_jsx$props.style === undefined ? _jsx$props_2() : _jsx$props.style).color"),
+      "{out}"
+    );
+    assert!(out.contains("const Row = (_jsx$props) => { const _jsx$props_icon = _jsx$lazyDefault(() => ((() => {"), "{out}");
+    assert!(!out.contains("<i />"), "{out}");
+  }
+
+  #[test]
+  fn test_unsupported_destructured_props() {
+    let out = transform(br#"function Card({ /* shown */ title, // label
+      count }) { return <p>{title}{count}</p>; }"#);
+    assert!(out.contains("function Card(_jsx$props) {"), "{out}");
+
+    assert_eq!(
+      transform_err(br#"function Card({ title count }) { return <p>{title}</p>; }"#),
+      "Unsupported destructuring in component props"
+    );
+  }
+
+  #[test]
+  fn test_destructured_props_are_read_only() {
+    assert_eq!(
+      transform_err(br#"function Card({ count }) { count++; return <p>{count}</p>; }"#),
+      "Destructured component props are read-only"
+    );
+  }
+
+  #[test]
+  fn test_static_expressions_are_not_tracked() {
+    let out = transform(
+      br#"const TITLE = "Hello";
+      const SIZE = 2 * 8;
+      const theme = { color: "red" };
+      const [count, setCount] = ref(0);
+      let mutable = 1;
+      function Card(props) {
+        const local = `${TITLE}!`;
+        return <div $title={TITLE} $data-count={count()} prop:size={SIZE} style:color={theme.color} attr:x={mutable}>
+          {TITLE} {local} {SIZE + 1} {count()} {props.x} {mutable} {theme}
+          <Item label={TITLE} value={count()} />
+        </div>;
+      }"#,
+    );
+
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "title", TITLE);"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "data-count", () => count());"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0["size"] = SIZE;"#), "{out}");
+    // `theme` could be mutated to hold a reactive object
+    assert!(out.contains(r#"_jsx$trackCssProperty(_jsx$el0, "color", () => theme.color);"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "x", () => mutable, false);"#), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, TITLE);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el3, local);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el5, SIZE + 1);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el7, () => count());"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el9, () => props.x);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el11, () => mutable);"), "{out}");
+    // Objects aren't text, inserting them directly would change how they render
+    assert!(out.contains("_jsx$insertChild(_jsx$el13, () => theme);"), "{out}");
+    assert!(out.contains("Item({label: TITLE, get value() { return count() }, })"), "{out}");
+  }
+
+  #[test]
+  fn test_var_and_member_reads_are_tracked() {
+    let out = transform(
+      br#"const X = { n: 1 };
+      const items = [1];
+      function F(c) {
+        if (c) { var X = reactive({ n: 2 }); }
+        items.push(c);
+        return <p $t={X.n} $first={items[0]}>{X}</p>;
+      }"#,
+    );
+
+    assert!(out.contains("() => X.n"), "{out}");
+    assert!(out.contains("() => items[0]"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, () => X);"), "{out}");
+  }
+
+  #[test]
+  fn test_calls_to_static_functions_are_not_tracked() {
+    let out = transform(
+      br#"const TITLE = "Hello";
+      const double = (n) => n * 2;
+      const label = function (a, b) { const sep = " "; return `${a}${sep}${double(b)}`; };
+      const [count, setCount] = ref(0);
+      const state = reactive({ n: 1 });
+      const reads = () => count();
+      const member = (o) => o.n;
+      const later = async (n) => n;
+      let mutable = 1;
+      const readsLet = () => mutable;
+      function declared(n) { return n; }
+      function Card(double) {
+        return <p $a={label(TITLE, 1)} $b={reads()} $c={member(state)} $d={readsLet()} $e={later(1)} $f={declared(1)} $g={double(1)} />;
+      }
+      const a = <p $w={double(2)} $x={double(count())} $y={state.n} $z={setCount(1)} />;"#,
+    );
+
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "a", label(TITLE, 1));"#), "{out}");
+    for key in ["b", "c", "d", "e", "f", "g"] {
+      assert!(out.contains(&format!(r#"_jsx$trackAttribute(_jsx$el0, "{key}", () => "#)), "{key}: {out}");
+    }
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "w", double(2));"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "x", () => double(count()));"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "y", () => state.n);"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackAttribute(_jsx$el0, "z", () => setCount(1));"#), "{out}");
+  }
+
+  #[test]
+  fn test_shadowed_constants_are_tracked() {
+    let out = transform(
+      br#"const TITLE = "Hello";
+      function Card(TITLE) {
+        return <p>{TITLE}{(() => { const TITLE = 1; return <b>{TITLE}</b>; })()}</p>;
+      }"#,
+    );
+
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, () => TITLE);"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, TITLE);"), "{out}");
+  }
+
+  #[test]
+  fn test_untracked_markers() {
+    let out = transform(
+      br#"const a = <p $title={/* @once */ x()} $data-y={$static(y())} class:on={$static(active())} prop:z={w}>
+        {/* @once */ f(y)}{$static(g())}{h()}
+        <Item label={$static(label())} value={/* @once */ v()} size={s()} />
+      </p>;"#,
+    );
+
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "title", x());"#), "{out}");
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "data-y", y());"#), "{out}");
+    assert!(out.contains(r#"_jsx$el0.classList.toggle("on", !!(active()));"#), "{out}");
+    assert!(out.contains(r#"_jsx$trackProperty(_jsx$el0, "z", () => w);"#), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, f(y));"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el2, g());"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el3, () => h());"), "{out}");
+    assert!(out.contains("Item({label: label(), value: v(), get size() { return s() }, })"), "{out}");
+    assert!(!out.contains("$static"), "{out}");
+  }
+
+  #[test]
+  fn test_invalid_untracked_markers() {
+    assert!(transform_err(br#"<p $if={$static(a())} />"#).starts_with(r#""$static()" and"#));
+    assert!(transform_err(br#"<p on:click={/* @once */ a} />"#).starts_with(r#""$static()" and"#));
+    assert_eq!(transform_err(br#"<p>{$static(a, b)}</p>"#), r#""$static()" takes a single expression"#);
+    for src in [
+      &br#"<p>{a + $static(b)}</p>"#[..],
+      br#"<p>{$static(x).y}</p>"#,
+      br#"<p {...$static(o)} />"#,
+      br#"<p title={f($static(x))} />"#,
+      br#"const v = $static(x);"#,
+    ] {
+      assert!(transform_err(src).starts_with(r#""$static()" must wrap"#), "{}", String::from_utf8_lossy(src));
+    }
+  }
+
+  #[test]
+  fn test_constant_folding() {
+    let out = transform(
+      br#"const a = <div title={"a & \"b\""} data-n={1 + 2} aria-label={"x" + 1.5} style:color={`red`} $data-y={"y"}>
+        Hello {"world"}! {"<b>"} {0x10}{`tA`} {x}
+        <Item label={"x"}>{"child"} text</Item>
+      </div>;"#,
+    );
+
+    assert!(
+      out.contains(r#"`<div title="a &amp; &quot;b&quot;" data-n="3" aria-label="x1.5" style="color:red;">Hello world! &lt;b&gt; 16tA <!><!></div>`"#),
+      "{out}"
+    );
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "data-y", "y");"#), "{out}");
+    assert!(out.contains(r#"Item({label: "x", }, {default: () => ["child text"]})"#), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el2, () => x);"), "{out}");
+  }
+
+  #[test]
+  fn test_constants_that_are_not_folded() {
+    let out = transform(br#"const a = <p data-a={`${b}`}>{1e21}{"\1"}{""}{1 - 2}</p>;"#);
+
+    assert!(out.contains("`<p><!><!><!><!></p>`"), "{out}");
+    assert!(out.contains(r#"_jsx$setAttribute(_jsx$el0, "data-a", `${b}`);"#), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el1, 1e21);"), "{out}");
+  }
+
+  #[test]
+  fn test_identical_templates_are_shared() {
+    let out = transform(br#"const a = <li class="row">x</li>; const b = <li class="row">x</li>; const c = <li>y</li>;"#);
+
+    assert_eq!(out.matches(r#"_jsx$template(`<li class="row">x</li>`)"#).count(), 1, "{out}");
+    assert_eq!(out.matches("_jsx$template(").count(), 2, "{out}");
+  }
+
+  #[test]
+  fn test_shared_templates_module() {
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let mut state = GlobalState::new(None, GenOptions::default());
+    state.set_templates_import(Some("./templates.js".into()));
+
+    let mut a = Vec::new();
+    parser
+      .parse_jsx_templates(br#"const a = <li class="row">x</li>;"#, &mut state, &mut a)
+      .expect("Templates should generate");
+    let mut b = Vec::new();
+    parser
+      .parse_jsx_templates(br#"const b = <li class="row">x</li>; const c = <p />;"#, &mut state, &mut b)
+      .expect("Templates should generate");
+    let (a, b) = (String::from_utf8(a).unwrap(), String::from_utf8(b).unwrap());
+    let module = state
+      .generate_shared_templates()
+      .expect("Module should generate")
+      .expect("Templates should be shared");
+
+    assert!(!a.contains("_jsx$template"), "{a}");
+    assert!(a.contains(r#" } from "./templates.js";"#), "{a}");
+    assert_eq!(module.matches("export const _jsx$templ_").count(), 2, "{module}");
+    assert!(module.starts_with(r#"import { template as _jsx$template } from "jsx";"#), "{module}");
+    let var = a.split("import { ").nth(1).and_then(|s| s.split(' ').next()).unwrap();
+    assert!(b.contains(var), "{b}");
+  }
+
+  #[test]
+  fn test_template_hash_collisions() {
+    let mut state = GlobalState::new(None, GenOptions::default());
+    let var = state.template_var("<p></p>", None).expect("Template should be declared");
+    // Pretend another template got the same hash first
+    let call = state
+      .templates
+      .insert(var.clone(), "_jsx$template(`<b></b>`)".into())
+      .expect("Template should be stored");
+
+    let other = state.template_var("<p></p>", None).expect("Template should be declared");
+    assert_eq!(&*other, format!("{var}_1"));
+    assert_eq!(state.templates[&other], call);
+    assert_eq!(state.template_var("<p></p>", None).expect("Template should be declared"), other);
+  }
+
+  #[test]
+  fn test_compact_output() {
+    let source = br#"const a = <div>
+      <p>Hi {name()}</p>
+      {items.map((i) => <li>{`x
+  ${i}`}</li>)}
+    </div>;"#;
+    let out = transform_with(source, GenOptions { output_style: OutputStyle::Compact, ..Default::default() });
+
+    assert!(!out.contains("//"), "{out}");
+    assert!(!out.contains("\n\n"), "{out}");
+    assert!(out.contains("const a = (()=>{const _jsx$el0=_jsx$templ_"), "{out}");
+    assert!(out.contains("_jsx$insertChild(_jsx$el3,()=>name());"), "{out}");
+    // Template literals keep their line breaks
+    assert!(out.contains("()=>`x\n  ${i}`);return _jsx$el0;})()));return _jsx$el0;})();"), "{out}");
+
+    // Spaces that keep tokens apart stay
+    let out = transform_with(
+      br#"const a = <p $a={x-- > y} $b={x < !y} $c={x - -y} $d={typeof y} />;"#,
+      GenOptions { output_style: OutputStyle::Compact, ..Default::default() },
+    );
+    assert!(out.contains(r#""a",()=>x-- >y)"#), "{out}");
+    assert!(out.contains(r#""b",()=>x< !y)"#), "{out}");
+    assert!(out.contains(r#""c",()=>x- -y)"#), "{out}");
+    assert!(out.contains(r#""d",()=>typeof y)"#), "{out}");
+  }
+
+  #[test]
+  fn test_debug_output() {
+    let source = br#"function A() {
+  return <div>
+    <p>Hi {name()}</p>
+    <Item />
+  </div>;
+}"#;
+    let out = transform_with(source, GenOptions { output_style: OutputStyle::Debug, ..Default::default() });
+
+    assert!(out.contains("\n  return (() => {\n    const _jsx$el0 = _jsx$templ_"), "{out}");
+    assert!(out.contains("(); // <div> line 2\n"), "{out}");
+    assert!(out.contains("    const _jsx$el1 = _jsx$el0.firstChild; // <p> line 3\n"), "{out}");
+    assert!(out.contains("    const _jsx$el3 = _jsx$el1.firstChild.nextSibling; // {expression} line 3\n"), "{out}");
+    assert!(out.contains("    const _jsx$el4 = _jsx$el1.nextSibling; // <Item> line 4\n"), "{out}");
+    assert!(out.contains("\n    return _jsx$el0;\n  })();\n}"), "{out}");
+
+    // The grammar doesn't know TypeScript, that code is left as generated
+    let out = transform_with(
+      br#"const a = <p>{(n as number) + 1}</p>;"#,
+      GenOptions { output_style: OutputStyle::Debug, ..Default::default() },
+    );
+    assert!(out.contains("const a = /* Not laid out, this code couldn't be parsed */ (() => {\n"), "{out}");
+  }
+
+  #[test]
+  fn test_unknown_output_style() {
+    assert!("pretty".parse::<OutputStyle>().is_err());
+    assert_eq!("compact".parse::<OutputStyle>().ok(), Some(OutputStyle::Compact));
   }
 }
@@ -1,21 +1,38 @@
+mod escape;
+mod escape_tests;
 mod r#gen;
 mod gen_tests;
 mod html_entities;
+mod props;
+mod scope;
+mod style;
 mod utils;
 mod utils_tests;
+mod walker;
+mod walker_tests;
 
 use crate::error::ParserError;
+use escape::{escape, Context};
 use std::{
+  borrow::Cow,
   fmt::{Debug, Write},
-  fs::{self, File},
+  fs,
   io::Read,
   path::{Path, PathBuf},
 };
 use tree_sitter::{Language, Node, Parser, Query, QueryCapture, QueryCursor, QueryMatches, Tree};
-pub use utils::GlobalState;
-use utils::{is_jsx_element, is_reactive_kind, is_void_element};
+pub use style::OutputStyle;
+pub use utils::{GenOptions, GlobalState};
+use scope::StaticValue;
+use utils::{SlotsParam, is_reactive_kind, is_void_element, preserves_whitespace, Namespace};
 
 pub const VAR_PREF: &str = "_jsx$";
+/// Key given to `{...spread}` attributes, it can't clash with real attribute names.
+const SPREAD_KEY: &str = "...";
+/// Kind given to prop values that can't read a signal, they are set once instead of tracked.
+const STATIC_KIND: &str = "static_expression";
+/// Kind given to literal expressions folded into the template, their text is in `constant`.
+const CONSTANT_KIND: &str = "constant_expression";
 pub const Q_JSX_TEMPLATE: &str = include_str!("../../queries/jsx_template.scm");
 pub const Q_COMMENT_DIRECTIVE: &str = include_str!("../../queries/comment_directive.scm");
 
@@ -69,6 +86,91 @@ impl JsParser {
     Ok(if src_idx == 0 { srcbuf } else { outbuf })
   }
 
+  pub fn parse_jsx_templates(
+    &mut self,
+    source: &[u8],
+    state: &mut GlobalState,
+    outbuf: &mut Vec<u8>,
+  ) -> Result<(), ParserError> {
+    let tree = self.tree(source)?;
+    let (masked, tree) = utils::mask_event_modifiers(&mut self.parser, source, tree)?;
+    let edits = props::destructured_props(tree.root_node(), &masked, state)?;
+    let (source, tree) = if edits.is_empty() {
+      (masked, tree)
+    } else {
+      let rewritten = utils::apply_edits(&masked, &edits).ok_or_else(|| {
+        ParserError::msg("Destructured props can't be used in the defaults of a nested component's props", tree.root_node())
+      })?;
+      let tree = self.tree(&rewritten)?;
+      (Cow::Owned(rewritten), tree)
+    };
+    let source = &*source;
+    if source.windows(7).any(|w| w == b"$static") {
+      utils::check_static_references(tree.root_node(), source)?;
+    }
+    let matches = self.parse(tree.root_node(), source)?;
+
+    let templates = matches
+      .filter(|m| !is_comment_match(m.captures))
+      .map(|m| JsxTemplate::parse(m.captures, source))
+      .collect::<Result<Box<_>, ParserError>>()?;
+
+    let template_parts = templates
+      .iter()
+      .enumerate()
+      .rev()
+      .filter(|(i, template)| {
+        template.is_root
+          && !templates.iter().rev().take(templates.len() - 1 - i).any(|t| {
+            let range = t.start..t.end + 1;
+            range.contains(&template.start) && range.contains(&template.end)
+          })
+      })
+      .map(|(_, template)| template.parts(&templates, state).map(|parts| (template, parts)))
+      .collect::<Result<Box<_>, _>>()?;
+
+    let mut src_idx = 0;
+    if source.len() > outbuf.capacity() {
+      outbuf.reserve(source.len() - outbuf.capacity());
+    }
+    outbuf.extend_from_slice(state.generate_setup_js()?.as_bytes());
+
+    // Slots parameters declared for <slot> are spliced in between the replaced templates
+    let mut edits = templates
+      .iter()
+      .filter_map(|t| t.slots.as_ref()?.edit.as_ref())
+      .collect::<Vec<_>>();
+    edits.sort_by_key(|edit| edit.start);
+    edits.dedup_by_key(|edit| edit.start);
+
+    let mut edits = edits.into_iter().peekable();
+    for (template, parts) in template_parts.iter().rev() {
+      while let Some(edit) = edits.next_if(|edit| edit.start < template.start) {
+        outbuf.extend_from_slice(&source[src_idx..edit.start]);
+        outbuf.extend_from_slice(edit.text.as_bytes());
+        src_idx = edit.end;
+      }
+      outbuf.extend_from_slice(&source[src_idx..template.start]);
+      let line_start = source[..template.start].iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+      let indent = &source[line_start..template.start];
+      let indent = str::from_utf8(&indent[..indent.iter().take_while(|b| b.is_ascii_whitespace()).count()])?;
+      let create_fn = style::format(&parts.create_fn, state.options.output_style, indent, &mut state.style_parser)?;
+      outbuf.extend_from_slice(create_fn.as_bytes());
+      src_idx = template.end;
+    }
+    for edit in edits {
+      outbuf.extend_from_slice(&source[src_idx..edit.start]);
+      outbuf.extend_from_slice(edit.text.as_bytes());
+      src_idx = edit.end;
+    }
+
+    if src_idx < source.len() {
+      outbuf.extend_from_slice(&source[src_idx..]);
+    }
+
+    Ok(())
+  }
+
   pub fn tree<'a>(&'a mut self, source: &'a [u8]) -> Result<Tree, ParserError> {
     self.parser.parse(source, None).ok_or(ParserError::Parse)
   }
@@ -92,15 +194,32 @@ struct Prop<'a> {
   kind: &'a str,
   key: &'a str,
   value: Option<&'a str>,
+  /// Text of a literal value folded into the template.
+  constant: Option<Box<str>>,
   node: Node<'a>,
 }
 
+/// Role of an element inside an `$if`/`$elseif`/`$else` chain or a `$switch` parent.
+#[derive(Debug)]
+enum Branch<'a> {
+  ElseIf(Prop<'a>),
+  Else(Prop<'a>),
+  Case(Prop<'a>),
+  Default(Prop<'a>),
+}
+
 #[derive(Debug)]
 struct Child<'a> {
   start: usize,
   end: usize,
   kind: &'a str,
+  /// Expression marked untracked, or that can't read a signal and is always text, it's inserted once.
+  is_static: bool,
+  /// The expression inside `{}` without comments and `$static()`, the node itself for other children.
+  expr: Node<'a>,
   value: &'a str,
+  /// Text of a literal expression folded into the template.
+  constant: Option<Box<str>>,
   node: Node<'a>,
 }
 
@@ -112,38 +231,58 @@ impl<'a> PartialEq<Child<'a>> for JsxTemplate<'a> {
 
 #[derive(Debug, Default)]
 pub struct JsxTemplate<'a> {
-  id: usize,
   pub start: usize,
   pub end: usize,
   tag: &'a str,
+  component: bool,
+  namespace: Namespace,
+  preserve_whitespace: bool,
   is_self_closing: bool,
   pub is_root: bool,
+  /// Line of the element in the source file.
+  line: usize,
   conditional: Option<Prop<'a>>,
+  branch: Option<Branch<'a>>,
+  switch: Option<Prop<'a>>,
   transition: Option<(Box<str>, Prop<'a>)>,
+  /// `$key` value, the element is recreated whenever it changes.
+  key: Option<Prop<'a>>,
+  /// `$show` condition and the name of the `$transition` it runs.
+  show: Option<(Prop<'a>, Option<Box<str>>)>,
+  /// Slots parameter of the component function enclosing a `<slot>`.
+  slots: Option<SlotsParam<'a>>,
+  /// `let:` props receiving the values a scoped slot passes back.
+  lets: Vec<Prop<'a>>,
   props: Vec<Prop<'a>>,
   children: Vec<Child<'a>>,
 }
 
 impl<'a> JsxTemplate<'a> {
+  /// Capitalized tags and member expressions (`<ui.Button>`, `<this.Row>`) are components.
+  /// Lowercase identifiers bound to a component in scope opt in with `$component`.
   fn is_component(&self) -> bool {
-    self.tag.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+    self.component
   }
 
-  pub fn parse(id: usize, captures: &'a [QueryCapture<'a>], source: &'a [u8]) -> Result<Self, ParserError> {
+  pub fn parse(captures: &'a [QueryCapture<'a>], source: &'a [u8]) -> Result<Self, ParserError> {
     enum CaptureIdx {
       Tag,
       Key,
       Value,
+      Spread,
       Children,
       Element,
     }
 
     let mut ret = Self::default();
+    let mut element = None;
 
     for cap in captures {
       match cap.index {
         x if x == CaptureIdx::Tag as u32 => {
           ret.tag = cap.node.utf8_text(source)?;
+          ret.component = cap.node.kind() == "member_expression"
+            || (cap.node.kind() == "identifier" && ret.tag.starts_with(|c: char| c.is_ascii_uppercase()));
           if ret.is_self_closing {
             ret.is_self_closing = is_void_element(ret.tag);
           }
@@ -153,14 +292,34 @@ impl<'a> JsxTemplate<'a> {
             kind: cap.node.kind(),
             key: cap.node.utf8_text(source)?,
             value: None,
+            constant: None,
             node: cap.node,
           });
         }
         x if x == CaptureIdx::Value as u32 => {
           let (is_conditional, transition) = if let Some(p) = ret.props.last_mut() {
-            p.kind = cap.node.kind();
-            p.value = Some(cap.node.utf8_text(source)?);
-            p.node = cap.node;
+            let untracked = utils::unwrap_static_call(cap.node, source)?;
+            let node = untracked.unwrap_or(cap.node);
+            p.kind = node.kind();
+            p.value = Some(node.utf8_text(source)?);
+            p.node = node;
+            if untracked.is_some() || utils::follows_once_comment(cap.node, source)? {
+              if !skips_static_tracking(p.key) {
+                return Err(ParserError::msg(
+                  "\"$static()\" and \"/* @once */\" only work on attributes, props and children",
+                  cap.node,
+                ));
+              }
+              p.kind = STATIC_KIND;
+            }
+            if folds_into_template(p.key)
+              && let Some(text) = scope::constant_text(node, source)
+            {
+              p.kind = CONSTANT_KIND;
+              p.constant = Some(text.into());
+            } else if is_reactive_kind(p.kind) && skips_static_tracking(p.key) && scope::static_value(node, source).is_some() {
+              p.kind = STATIC_KIND;
+            }
             (
               p.key == "$if",
               p.key
@@ -177,30 +336,47 @@ impl<'a> JsxTemplate<'a> {
             ret.transition = ret.props.pop().map(|prop| (transition_name.into(), prop));
           }
         }
-        x if x == CaptureIdx::Children as u32 => ret.children.push(Child {
-          start: cap.node.start_byte(),
-          end: cap.node.end_byte(),
-          kind: cap.node.kind(),
-          value: if cap.node.kind() == "jsx_expression" {
-            cap
-              .node
-              .named_child(0)
-              .ok_or_else(|| ParserError::empty_jsx_expression(cap.node))?
-              .utf8_text(source)?
+        x if x == CaptureIdx::Spread as u32 => {
+          ret.props.push(Prop {
+            kind: cap.node.kind(),
+            key: SPREAD_KEY,
+            value: Some(cap.node.utf8_text(source)?),
+            constant: None,
+            node: cap.node,
+          });
+        }
+        x if x == CaptureIdx::Children as u32 => {
+          let mut constant = None;
+          let (expr, is_static) = if cap.node.kind() == "jsx_expression" {
+            let (expr, once) = utils::untracked_expression(cap.node, source)?;
+            let expr = expr.ok_or_else(|| ParserError::empty_jsx_expression(cap.node))?;
+            // An empty text node wouldn't survive the template
+            constant = scope::constant_text(expr, source).filter(|text| !text.is_empty());
+            (expr, once || scope::static_value(expr, source) == Some(StaticValue::Text))
           } else {
-            cap.node.utf8_text(source)?
-          },
-          node: cap.node,
-        }),
+            (cap.node, false)
+          };
+          ret.children.push(Child {
+            start: cap.node.start_byte(),
+            end: cap.node.end_byte(),
+            kind: if constant.is_some() { CONSTANT_KIND } else { cap.node.kind() },
+            is_static,
+            value: expr.utf8_text(source)?,
+            constant: constant.map(Into::into),
+            node: cap.node,
+            expr,
+          });
+        }
         x if x == CaptureIdx::Element as u32 => {
           ret.start = cap.node.start_byte();
+          ret.line = cap.node.start_position().row + 1;
           ret.end = cap.node.end_byte();
           ret.is_self_closing = cap.node.kind() == "jsx_self_closing_element";
           ret.is_root = !cap
             .node
             .parent()
             .is_some_and(|n| matches!(n.kind(), "jsx_element" | "jsx_self_closing_element"));
-          ret.id = id;
+          element = Some(cap.node);
         }
         _ => (),
       }
@@ -208,6 +384,158 @@ impl<'a> JsxTemplate<'a> {
 
     if ret.tag.is_empty() {
       ret.tag = "template";
+    } else if let Some(element) = element {
+      ret.namespace = Namespace::of(element, ret.tag, source);
+    }
+
+    if let Some(element) = element {
+      ret.preserve_whitespace = preserves_whitespace(element, source);
+    }
+    if let Some(prop) = ret
+      .props
+      .iter()
+      .find(|p| p.key == "$preserveWhitespace" && p.value.is_some() && !matches!(p.node.kind(), "true" | "false"))
+    {
+      return Err(ParserError::msg(
+        "\"$preserveWhitespace\" must be used without a value or with true or false, text is laid out at compile time",
+        prop.node,
+      ));
+    }
+    ret.props.retain(|p| p.key != "$preserveWhitespace");
+
+    for i in (0..ret.props.len()).rev() {
+      let key = ret.props[i].key;
+      if !matches!(key, "$elseif" | "$else" | "$case" | "$default" | "$switch") {
+        continue;
+      }
+
+      let prop = ret.props.remove(i);
+      let has_value = prop.value.is_some();
+      if matches!(key, "$else" | "$default") == has_value {
+        return Err(ParserError::msg(
+          if has_value {
+            "\"$else\" and \"$default\" must not have a value"
+          } else {
+            "\"$elseif\", \"$case\" and \"$switch\" must have a value"
+          },
+          prop.node,
+        ));
+      }
+      if key == "$switch" {
+        ret.switch = Some(prop);
+        continue;
+      }
+      if ret.branch.is_some() || ret.conditional.is_some() {
+        return Err(ParserError::msg("Only one of $if, $elseif, $else, $case or $default can be used", prop.node));
+      }
+      ret.branch = Some(match key {
+        "$elseif" => Branch::ElseIf(prop),
+        "$else" => Branch::Else(prop),
+        "$case" => Branch::Case(prop),
+        _ => Branch::Default(prop),
+      });
+    }
+
+    if let Some(i) = ret.props.iter().position(|p| p.key == "$show") {
+      let prop = ret.props.remove(i);
+      if prop.value.is_none() {
+        return Err(ParserError::msg("\"$show\" must have a value", prop.node));
+      }
+      if let Some((_, transition)) = &ret.transition {
+        return Err(ParserError::msg(
+          "\"$transition\" must not have a value when used with \"$show\"",
+          transition.node,
+        ));
+      }
+      let transition = ret
+        .props
+        .iter()
+        .position(|p| p.key == "$transition" || p.key.starts_with("$transition:"))
+        .map(|i| {
+          let key = ret.props.remove(i).key;
+          key.strip_prefix("$transition:").unwrap_or("jsx").into()
+        });
+      ret.show = Some((prop, transition));
+    }
+
+    // Transitions with a value were taken with it, this one only names the transition of `$if`
+    let named_transition = ret
+      .props
+      .iter()
+      .position(|p| p.key == "$transition" || p.key.starts_with("$transition:"))
+      .map(|i| ret.props.remove(i));
+    if let Some(Branch::ElseIf(_) | Branch::Else(_)) = &ret.branch
+      && let Some(transition) = named_transition.as_ref().or(ret.transition.as_ref().map(|(_, t)| t))
+    {
+      return Err(ParserError::msg(
+        "\"$transition\" can't be used on \"$elseif\" or \"$else\"",
+        transition.node,
+      ));
+    }
+    if let Some(prop) = named_transition {
+      let Some(cond) = ret.conditional.take() else {
+        return Err(ParserError::msg(
+          "\"$transition\" without a value must be used with \"$if\" or \"$show\"",
+          prop.node,
+        ));
+      };
+      ret.transition = Some((prop.key.strip_prefix("$transition:").unwrap_or("jsx").into(), cond));
+    }
+    if let (Some(_), Some((_, transition))) = (&ret.conditional, &ret.transition) {
+      return Err(ParserError::msg(
+        "\"$transition\" must not have a value when used with \"$if\"",
+        transition.node,
+      ));
+    }
+
+    if let Some(i) = ret.props.iter().position(|p| p.key == "$key") {
+      let prop = ret.props.remove(i);
+      if prop.value.is_none() {
+        return Err(ParserError::msg("\"$key\" must have a value", prop.node));
+      }
+      if ret.conditional.is_some() || ret.branch.is_some() || ret.transition.is_some() {
+        return Err(ParserError::msg(
+          "\"$key\" can't be combined with \"$if\", \"$transition\" or branches, wrap the element instead",
+          prop.node,
+        ));
+      }
+      ret.key = Some(prop);
+    }
+
+    if let Some(i) = ret.props.iter().position(|p| p.key == "$component") {
+      let prop = ret.props.remove(i);
+      if prop.value.is_some() {
+        return Err(ParserError::msg("\"$component\" must not have a value", prop.node));
+      }
+      ret.component = true;
+    }
+
+    let (lets, props) = std::mem::take(&mut ret.props)
+      .into_iter()
+      .partition::<Vec<_>, _>(|p| p.key.starts_with("let:"));
+    ret.props = props;
+    ret.lets = lets;
+    if let Some(prop) = ret.lets.first() {
+      if !ret.component && !ret.props.iter().any(|p| p.key == "slot") {
+        return Err(ParserError::msg(
+          "\"let:\" only works on components and elements with a \"slot\" attribute",
+          prop.node,
+        ));
+      }
+      if let Some(prop) = ret
+        .lets
+        .iter()
+        .find(|p| p.value.is_some() && p.kind != "identifier")
+      {
+        return Err(ParserError::msg("\"let:\" can only be renamed to an identifier", prop.node));
+      }
+    }
+
+    if ret.tag == "slot"
+      && !ret.component
+      && let Some(element) = element
+    {
+      ret.slots = Some(utils::slots_param(element, source)?);
     }
 
     Ok(ret)
@@ -220,10 +548,14 @@ impl<'a> JsxTemplate<'a> {
     templates: &[JsxTemplate],
     state: &mut GlobalState,
   ) -> Result<(), ParserError> {
-    let (elem_vars, elem_hooks) = self.generate_fn(var_idx, templates, state)?;
+    let outer = std::mem::take(&mut state.walk);
+    let generated = self.generate_fn(var_idx, templates, state);
+    let walk = std::mem::replace(&mut state.walk, outer);
+    let (elem_vars, elem_hooks) = generated?;
+    let steps = walker::minimal_walk(&walk, &mut state.walk_stats)?;
     write!(
       ret.create_fn,
-      "(() => {{\n{elem_vars}\n{elem_hooks}\nreturn {VAR_PREF}el0;\n}})()"
+      "(() => {{\n{elem_vars}{steps}\n{elem_hooks}\nreturn {VAR_PREF}el0;\n}})()"
     )?;
 
     Ok(())
@@ -239,21 +571,7 @@ impl<'a> JsxTemplate<'a> {
     };
 
     if self.tag == "template" {
-      write!(ret.create_fn, "[")?;
-      let mut idx = 0;
-      while let Some(c) = self.children.get(idx) {
-        state.is_template_child = is_jsx_element(c.kind);
-        let Some(value) = self.child_as_value(&mut idx, c, templates, state)? else {
-          continue;
-        };
-
-        if is_reactive_kind(c.kind) {
-          write!(ret.create_fn, "() => {value}, ")?;
-        } else {
-          write!(ret.create_fn, "{value}, ")?;
-        }
-      }
-      writeln!(ret.create_fn, "]")?;
+      ret.create_fn = self.generate_fragment(templates, state)?;
     } else {
       let mut var_idx = 0;
       self.write_fn(&mut ret, &mut var_idx, templates, state)?;
@@ -263,6 +581,38 @@ impl<'a> JsxTemplate<'a> {
   }
 }
 
+/// The query matches an attribute like `{/* comment */ value}` once per child of the braces,
+/// only the match capturing the value is kept.
+fn is_comment_match(captures: &[QueryCapture]) -> bool {
+  captures.iter().any(|cap| {
+    cap.node.kind() == "comment"
+      && cap.node.parent().is_some_and(|p| {
+        let mut cursor = p.walk();
+        p.kind() == "jsx_expression" && p.named_children(&mut cursor).any(|c| c.kind() != "comment")
+      })
+  })
+}
+
+/// Props whose static values can skip tracking, directives and `class:` need a tracked value.
+fn skips_static_tracking(key: &str) -> bool {
+  match key.split_once(':') {
+    Some((namespace, _)) => matches!(namespace, "prop" | "attr" | "class" | "style" | "var"),
+    None => !matches!(
+      key,
+      "$if" | "$elseif" | "$switch" | "$case" | "$show" | "$key" | "$transition" | "$ref" | "$refFn" | "slot"
+    ),
+  }
+}
+
+/// Attributes written in the template when their value is a literal.
+fn folds_into_template(key: &str) -> bool {
+  match key.split_once(':') {
+    _ if key.starts_with('$') => false,
+    Some((namespace, _)) => matches!(namespace, "attr" | "style" | "var") || utils::is_namespaced_attribute(key),
+    None => key != "slot",
+  }
+}
+
 #[derive(Debug, Default)]
 pub struct FileContentImport {
   pub start: usize,
@@ -286,39 +636,7 @@ impl FileContentImport {
   }
 
   pub fn contents(&self, src_dir: &Path) -> Result<Vec<u8>, ParserError> {
-    let mut f = File::open(src_dir.join(&self.path))?;
-    let mut contents = Vec::with_capacity(f.metadata()?.len() as usize + 2);
-    contents.push(b'`');
-    f.read_to_end(&mut contents)?;
-
-    let mut inside_backticks = false;
-    let mut i = 1;
-
-    while i < contents.len() {
-      match contents[i] {
-        b'`' => {
-          if i > 0 && contents[i - 1] != b'\\' {
-            inside_backticks = !inside_backticks;
-          }
-
-          contents.insert(i, b'\\');
-          i += 1;
-        }
-        b'\\' => {
-          contents.insert(i, b'\\');
-          i += 1;
-        }
-        b'$' if i + 1 < contents.len() && contents[i + 1] == b'{' => {
-          contents.insert(i, b'\\');
-          i += 1;
-        }
-        _ => (),
-      }
-
-      i += 1;
-    }
-
-    contents.push(b'`');
-    Ok(contents)
+    let contents = fs::read_to_string(src_dir.join(&self.path))?;
+    Ok(format!("`{}`", escape(&contents, Context::TemplateLiteral)).into_bytes())
   }
 }
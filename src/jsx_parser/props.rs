@@ -0,0 +1,303 @@
+use super::{
+  scope,
+  utils::{apply_edits, is_component_function, is_function_kind, is_jsx_element, GlobalState, SourceEdit},
+  VAR_PREF,
+};
+use crate::error::ParserError;
+use std::fmt::Write;
+use tree_sitter::Node;
+
+/// A prop destructured in a component's parameters and the expression reading it from the props object.
+struct Binding<'a> {
+  name: &'a str,
+  read: String,
+}
+
+/// Default value of a destructured prop, evaluated once per call, the first time the prop is `undefined`.
+struct PropDefault<'a> {
+  var: String,
+  value: Node<'a>,
+  /// Bindings declared before the default, the only ones it can read.
+  visible: usize,
+}
+
+/// Rewrites components declared as `function Card({ title, count = 0, ...rest })` to take the props object
+/// and read `title`, `count` and `rest` from it on every use, so the getters passed for reactive props
+/// aren't read once when the component is called.
+pub(super) fn destructured_props(
+  root: Node,
+  source: &[u8],
+  state: &mut GlobalState,
+) -> Result<Vec<SourceEdit>, ParserError> {
+  let mut edits = Vec::new();
+  let mut functions = Vec::new();
+  find_components(root, source, &mut functions);
+
+  for function in functions {
+    let Some(param) = function
+      .child_by_field_name("parameters")
+      .and_then(|params| params.named_child(0))
+      .filter(|p| p.kind() != "comment")
+    else {
+      continue;
+    };
+    let pattern = match param.kind() {
+      "object_pattern" => param,
+      "assignment_pattern" => match param.child_by_field_name("left") {
+        Some(left) if left.kind() == "object_pattern" => left,
+        _ => continue,
+      },
+      _ => continue,
+    };
+
+    let mut bindings = Vec::new();
+    let mut defaults = Vec::new();
+    collect_bindings(pattern, &format!("{VAR_PREF}props"), source, &mut bindings, &mut defaults, state)?;
+    edits.push(SourceEdit {
+      start: param.start_byte(),
+      end: param.end_byte(),
+      text: format!("{VAR_PREF}props"),
+    });
+
+    let Some(body) = function.child_by_field_name("body") else {
+      continue;
+    };
+    if !defaults.is_empty() {
+      state.imports.insert("lazyDefault");
+      let mut declarations = String::new();
+      for default in &defaults {
+        let value = rewritten_text(default.value, function, &bindings[..default.visible], source)?;
+        write!(declarations, "const {} = {VAR_PREF}lazyDefault(() => ({value})); ", default.var)?;
+      }
+      // Arrow functions returning an expression get a block to declare them in
+      if body.kind() == "statement_block" {
+        edits.push(SourceEdit {
+          start: body.start_byte() + 1,
+          end: body.start_byte() + 1,
+          text: format!(" {declarations}"),
+        });
+      } else {
+        edits.push(SourceEdit {
+          start: body.start_byte(),
+          end: body.start_byte(),
+          text: format!("{{ {declarations}return ("),
+        });
+        edits.push(SourceEdit {
+          start: body.end_byte(),
+          end: body.end_byte(),
+          text: "); }".to_string(),
+        });
+      }
+    }
+    rewrite_reads(body, function, &bindings, source, &mut edits)?;
+  }
+
+  edits.sort_by_key(|edit| edit.start);
+  Ok(edits)
+}
+
+/// Capitalized functions returning JSX.
+fn find_components<'a>(node: Node<'a>, source: &[u8], functions: &mut Vec<Node<'a>>) {
+  if is_function_kind(node.kind())
+    && is_component_function(node, source)
+    && node.child_by_field_name("body").is_some_and(contains_jsx)
+  {
+    functions.push(node);
+  }
+
+  let mut cursor = node.walk();
+  for child in node.named_children(&mut cursor) {
+    find_components(child, source, functions);
+  }
+}
+
+fn contains_jsx(node: Node) -> bool {
+  if is_jsx_element(node.kind()) {
+    return true;
+  }
+  let mut cursor = node.walk();
+  node.named_children(&mut cursor).any(contains_jsx)
+}
+
+fn collect_bindings<'a>(
+  pattern: Node<'a>,
+  base: &str,
+  source: &'a [u8],
+  bindings: &mut Vec<Binding<'a>>,
+  defaults: &mut Vec<PropDefault<'a>>,
+  state: &mut GlobalState,
+) -> Result<(), ParserError> {
+  let mut keys = Vec::new();
+  let mut cursor = pattern.walk();
+  for prop in pattern.named_children(&mut cursor) {
+    match prop.kind() {
+      "shorthand_property_identifier_pattern" => {
+        let name = prop.utf8_text(source)?;
+        keys.push(format!("\"{name}\""));
+        bindings.push(Binding {
+          name,
+          read: format!("{base}.{name}"),
+        });
+      }
+      "object_assignment_pattern" => {
+        let (Some(left), Some(default)) = (prop.child_by_field_name("left"), prop.child_by_field_name("right")) else {
+          return Err(ParserError::msg("Invalid default value in component props", prop));
+        };
+        let name = left.utf8_text(source)?;
+        keys.push(format!("\"{name}\""));
+        let var = add_default(defaults, Some(name), default, bindings.len());
+        bindings.push(Binding {
+          name,
+          read: with_default(&format!("{base}.{name}"), &var),
+        });
+      }
+      "pair_pattern" => {
+        let (Some(key), Some(value)) = (prop.child_by_field_name("key"), prop.child_by_field_name("value")) else {
+          return Err(ParserError::msg("Invalid destructuring in component props", prop));
+        };
+        let (read, key) = match key.kind() {
+          "property_identifier" => {
+            let key = key.utf8_text(source)?;
+            (format!("{base}.{key}"), format!("\"{key}\""))
+          }
+          "computed_property_name" => {
+            let key = key.named_child(0).unwrap_or(key).utf8_text(source)?;
+            (format!("{base}[{key}]"), key.to_string())
+          }
+          _ => {
+            let key = key.utf8_text(source)?;
+            (format!("{base}[{key}]"), key.to_string())
+          }
+        };
+        keys.push(key);
+
+        match value.kind() {
+          "identifier" => bindings.push(Binding {
+            name: value.utf8_text(source)?,
+            read,
+          }),
+          "assignment_pattern" => match (value.child_by_field_name("left"), value.child_by_field_name("right")) {
+            (Some(left), Some(default)) if left.kind() == "identifier" => {
+              let name = left.utf8_text(source)?;
+              let var = add_default(defaults, Some(name), default, bindings.len());
+              bindings.push(Binding {
+                name,
+                read: with_default(&read, &var),
+              });
+            }
+            (Some(left), Some(default)) if left.kind() == "object_pattern" => {
+              let var = add_default(defaults, None, default, bindings.len());
+              collect_bindings(left, &with_default(&read, &var), source, bindings, defaults, state)?;
+            }
+            _ => return Err(ParserError::msg("Only object patterns are supported in component props", value)),
+          },
+          "object_pattern" => collect_bindings(value, &read, source, bindings, defaults, state)?,
+          _ => return Err(ParserError::msg("Only object patterns are supported in component props", value)),
+        }
+      }
+      "rest_pattern" => {
+        let Some(name) = prop.named_child(0).filter(|n| n.kind() == "identifier") else {
+          return Err(ParserError::msg("Only object patterns are supported in component props", prop));
+        };
+        state.imports.insert("omitProps");
+        let mut read = format!("{VAR_PREF}omitProps({base}, [");
+        for key in &keys {
+          write!(read, "{key}, ")?;
+        }
+        write!(read, "])")?;
+        bindings.push(Binding {
+          name: name.utf8_text(source)?,
+          read,
+        });
+      }
+      "comment" => (),
+      _ => return Err(ParserError::msg("Unsupported destructuring in component props", prop)),
+    }
+  }
+
+  Ok(())
+}
+
+/// Variable holding the default, named after its binding, or numbered for defaults of nested patterns.
+fn add_default<'a>(defaults: &mut Vec<PropDefault<'a>>, name: Option<&str>, value: Node<'a>, visible: usize) -> String {
+  let var = match name {
+    Some(name) => format!("{VAR_PREF}props_{name}"),
+    None => format!("{VAR_PREF}props_{}", defaults.len()),
+  };
+  defaults.push(PropDefault {
+    var: var.clone(),
+    value,
+    visible,
+  });
+  var
+}
+
+/// Defaults apply when the prop is `undefined`, like they do when destructuring.
+fn with_default(read: &str, default_var: &str) -> String {
+  format!("({read} === undefined ? {default_var}() : {read})")
+}
+
+/// Source of `node` with the reads of destructured props rewritten.
+fn rewritten_text(node: Node, function: Node, bindings: &[Binding], source: &[u8]) -> Result<String, ParserError> {
+  let mut edits = Vec::new();
+  rewrite_reads(node, function, bindings, source, &mut edits)?;
+  for edit in &mut edits {
+    edit.start -= node.start_byte();
+    edit.end -= node.start_byte();
+  }
+  let text = apply_edits(&source[node.byte_range()], &edits)
+    .ok_or_else(|| ParserError::msg("Invalid default value in component props", node))?;
+  Ok(std::str::from_utf8(&text)?.to_string())
+}
+
+fn rewrite_reads(
+  node: Node,
+  function: Node,
+  bindings: &[Binding],
+  source: &[u8],
+  edits: &mut Vec<SourceEdit>,
+) -> Result<(), ParserError> {
+  if matches!(node.kind(), "identifier" | "shorthand_property_identifier") {
+    let name = node.utf8_text(source)?;
+    let Some(binding) = bindings.iter().find(|b| b.name == name) else {
+      return Ok(());
+    };
+    // `<title>` is an element, not the `title` prop
+    let is_tag = node.parent().is_some_and(|p| {
+      matches!(p.kind(), "jsx_opening_element" | "jsx_closing_element" | "jsx_self_closing_element")
+        && p.child_by_field_name("name") == Some(node)
+    });
+    if is_tag || scope::declaration(node, name, Some(function), source).is_some() {
+      return Ok(());
+    }
+
+    if let Some(parent) = node.parent() {
+      let is_target = match parent.kind() {
+        "assignment_expression" | "augmented_assignment_expression" => parent.child_by_field_name("left") == Some(node),
+        "update_expression" => true,
+        _ => false,
+      };
+      if is_target {
+        return Err(ParserError::msg("Destructured component props are read-only", node));
+      }
+    }
+
+    edits.push(SourceEdit {
+      start: node.start_byte(),
+      end: node.end_byte(),
+      text: if node.kind() == "shorthand_property_identifier" {
+        format!("{name}: {}", binding.read)
+      } else {
+        binding.read.clone()
+      },
+    });
+    return Ok(());
+  }
+
+  let mut cursor = node.walk();
+  for child in node.named_children(&mut cursor) {
+    rewrite_reads(child, function, bindings, source, edits)?;
+  }
+
+  Ok(())
+}
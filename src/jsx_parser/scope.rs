@@ -0,0 +1,441 @@
+use super::utils::is_function_kind;
+use std::borrow::Cow;
+use tree_sitter::Node;
+
+/// How a name is bound where it's used.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Declaration<'a> {
+  /// `const name = value`
+  Const(Node<'a>),
+  /// Parameters, `let`, `var`, imports, functions and destructured names.
+  Other,
+}
+
+/// What a static expression evaluates to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum StaticValue {
+  /// Strings and numbers, rendered as text when used as a child.
+  Text,
+  Other,
+}
+
+/// Finds the declaration `name` refers to at `node`, looking through the enclosing scopes up to `until`.
+/// `None` means the name is global or declared outside `until`.
+pub(super) fn declaration<'a>(node: Node<'a>, name: &str, until: Option<Node>, source: &[u8]) -> Option<Declaration<'a>> {
+  let mut parent = node.parent();
+  while let Some(scope) = parent.filter(|p| Some(*p) != until) {
+    let declaration = if is_function_kind(scope.kind()) {
+      let declares = scope
+        .child_by_field_name("parameters")
+        .or_else(|| scope.child_by_field_name("parameter"))
+        .is_some_and(|params| pattern_declares(params, name, source))
+        || (scope.kind() == "function_expression"
+          && scope
+            .child_by_field_name("name")
+            .is_some_and(|n| n.utf8_text(source).is_ok_and(|n| n == name)))
+        || scope
+          .child_by_field_name("body")
+          .is_some_and(|body| var_declares(body, name, source));
+      declares.then_some(Declaration::Other)
+    } else {
+      match scope.kind() {
+        "statement_block" | "program" | "switch_body" | "class_body" => {
+          let mut cursor = scope.walk();
+          let mut statements = scope.named_children(&mut cursor);
+          statements
+            .find_map(|statement| statement_declares(statement, name, source))
+            .or_else(|| (scope.kind() == "program" && var_declares(scope, name, source)).then_some(Declaration::Other))
+        }
+        "for_statement" => scope
+          .child_by_field_name("initializer")
+          .and_then(|init| statement_declares(init, name, source))
+          .map(|_| Declaration::Other),
+        "for_in_statement" => scope
+          .child_by_field_name("left")
+          .is_some_and(|left| pattern_declares(left, name, source))
+          .then_some(Declaration::Other),
+        "catch_clause" => scope
+          .child_by_field_name("parameter")
+          .is_some_and(|param| pattern_declares(param, name, source))
+          .then_some(Declaration::Other),
+        _ => None,
+      }
+    };
+    if declaration.is_some() {
+      return declaration;
+    }
+    parent = scope.parent();
+  }
+
+  None
+}
+
+fn statement_declares<'a>(statement: Node<'a>, name: &str, source: &[u8]) -> Option<Declaration<'a>> {
+  match statement.kind() {
+    "lexical_declaration" | "variable_declaration" => {
+      let is_const = statement.child(0).is_some_and(|k| k.kind() == "const");
+      let mut cursor = statement.walk();
+      let mut declarators = statement.named_children(&mut cursor);
+      declarators.find_map(|declarator| {
+        let pattern = declarator.child_by_field_name("name")?;
+        if !pattern_declares(pattern, name, source) {
+          return None;
+        }
+        match declarator.child_by_field_name("value") {
+          Some(value) if is_const && pattern.kind() == "identifier" => Some(Declaration::Const(value)),
+          _ => Some(Declaration::Other),
+        }
+      })
+    }
+    "function_declaration" | "generator_function_declaration" | "class_declaration" => statement
+      .child_by_field_name("name")
+      .is_some_and(|n| n.utf8_text(source).is_ok_and(|n| n == name))
+      .then_some(Declaration::Other),
+    "export_statement" => statement
+      .child_by_field_name("declaration")
+      .and_then(|declaration| statement_declares(declaration, name, source)),
+    "import_statement" => pattern_declares(statement, name, source).then_some(Declaration::Other),
+    _ => None,
+  }
+}
+
+/// Whether a `var` anywhere in `node` declares `name`, they belong to the enclosing function
+/// no matter how deep in blocks they are.
+fn var_declares(node: Node, name: &str, source: &[u8]) -> bool {
+  let mut cursor = node.walk();
+  let mut children = node.named_children(&mut cursor);
+  children.any(|child| match child.kind() {
+    "variable_declaration" => {
+      let mut cursor = child.walk();
+      let mut declarators = child.named_children(&mut cursor);
+      declarators.any(|d| d.child_by_field_name("name").is_some_and(|p| pattern_declares(p, name, source)))
+    }
+    "for_in_statement" if child.child(1).is_some_and(|k| k.kind() == "var") => {
+      child
+        .child_by_field_name("left")
+        .is_some_and(|left| pattern_declares(left, name, source))
+        || var_declares(child, name, source)
+    }
+    kind if is_function_kind(kind) || kind == "class_body" => false,
+    _ => var_declares(child, name, source),
+  })
+}
+
+pub(super) fn pattern_declares(pattern: Node, name: &str, source: &[u8]) -> bool {
+  match pattern.kind() {
+    "identifier" | "shorthand_property_identifier_pattern" => pattern.utf8_text(source).is_ok_and(|n| n == name),
+    "pair_pattern" => pattern
+      .child_by_field_name("value")
+      .is_some_and(|value| pattern_declares(value, name, source)),
+    "assignment_pattern" | "object_assignment_pattern" => pattern
+      .child_by_field_name("left")
+      .is_some_and(|left| pattern_declares(left, name, source)),
+    // `import { a as b }` binds `b`
+    "import_specifier" => pattern
+      .child_by_field_name("alias")
+      .or_else(|| pattern.child_by_field_name("name"))
+      .is_some_and(|n| n.utf8_text(source).is_ok_and(|n| n == name)),
+    "string" => false,
+    _ => {
+      let mut cursor = pattern.walk();
+      pattern
+        .named_children(&mut cursor)
+        .any(|child| pattern_declares(child, name, source))
+    }
+  }
+}
+
+/// Limits how many `const` bindings are followed, it also stops `const a = b, b = a` cycles.
+const MAX_DEPTH: usize = 16;
+
+/// What `expr` evaluates to if it can't read a signal, so tracking it would never update anything.
+/// Literals, operators on them, `const` bindings to them and calls with static arguments to `const`
+/// functions that read no signal qualify. Bindings from `ref()` and `reactive()`, like other calls,
+/// parameters and imports, could all read a signal. Reading a property is never static either, a
+/// `const` object can still be mutated to hold a `reactive()` one.
+pub(super) fn static_value(expr: Node, source: &[u8]) -> Option<StaticValue> {
+  static_value_at(expr, source, 0)
+}
+
+fn static_value_at(expr: Node, source: &[u8], depth: usize) -> Option<StaticValue> {
+  if depth > MAX_DEPTH {
+    return None;
+  }
+  let all_static = |node: Node| {
+    let mut cursor = node.walk();
+    let mut children = node.named_children(&mut cursor);
+    children.try_fold(StaticValue::Text, |value, child| {
+      if child.kind() == "comment" {
+        return Some(value);
+      }
+      match static_value_at(child, source, depth + 1)? {
+        StaticValue::Text => Some(value),
+        StaticValue::Other => Some(StaticValue::Other),
+      }
+    })
+  };
+
+  match expr.kind() {
+    // BigInts are numbers in the grammar but not text for `insertChild`
+    "number" => Some(match expr.utf8_text(source).ok()?.ends_with('n') {
+      true => StaticValue::Other,
+      false => StaticValue::Text,
+    }),
+    "string" | "string_fragment" | "escape_sequence" => Some(StaticValue::Text),
+    "true" | "false" | "null" => Some(StaticValue::Other),
+    "template_string" => all_static(expr).map(|_| StaticValue::Text),
+    "template_substitution" | "parenthesized_expression" | "spread_element" => all_static(expr),
+    "identifier" => {
+      let name = expr.utf8_text(source).ok()?;
+      match declaration(expr, name, None, source) {
+        Some(Declaration::Const(value)) => static_value_at(value, source, depth + 1),
+        Some(Declaration::Other) => None,
+        None => match name {
+          "NaN" | "Infinity" => Some(StaticValue::Text),
+          "undefined" => Some(StaticValue::Other),
+          _ => None,
+        },
+      }
+    }
+    "unary_expression" => {
+      let argument = static_value_at(expr.child_by_field_name("argument")?, source, depth + 1)?;
+      match expr.child_by_field_name("operator")?.kind() {
+        "typeof" => Some(StaticValue::Text),
+        "-" | "+" | "~" => Some(argument),
+        "!" | "void" => Some(StaticValue::Other),
+        _ => None,
+      }
+    }
+    "binary_expression" => {
+      let value = all_static(expr)?;
+      match expr.child_by_field_name("operator")?.kind() {
+        "+" | "-" | "*" | "/" | "%" | "**" | "&" | "|" | "^" | "<<" | ">>" | ">>>" | "&&" | "||" | "??" => Some(value),
+        // `in` and `instanceof` on a reactive proxy read it
+        "in" | "instanceof" => None,
+        _ => Some(StaticValue::Other),
+      }
+    }
+    "ternary_expression" => {
+      static_value_at(expr.child_by_field_name("condition")?, source, depth + 1)?;
+      let consequence = static_value_at(expr.child_by_field_name("consequence")?, source, depth + 1)?;
+      let alternative = static_value_at(expr.child_by_field_name("alternative")?, source, depth + 1)?;
+      Some(if consequence == alternative { consequence } else { StaticValue::Other })
+    }
+    "call_expression" => {
+      let callee = expr.child_by_field_name("function").filter(|f| f.kind() == "identifier")?;
+      let args = expr.child_by_field_name("arguments").filter(|a| a.kind() == "arguments")?;
+      all_static(args)?;
+      is_static_function(callee, source, depth + 1).then_some(StaticValue::Other)
+    }
+    "array" => all_static(expr).map(|_| StaticValue::Other),
+    "object" => {
+      let mut cursor = expr.walk();
+      let mut props = expr.named_children(&mut cursor);
+      props
+        .all(|prop| match prop.kind() {
+          "pair" => {
+            prop
+              .child_by_field_name("key")
+              .is_some_and(|key| key.kind() != "computed_property_name" || all_static(key).is_some())
+              && prop
+                .child_by_field_name("value")
+                .is_some_and(|value| static_value_at(value, source, depth + 1).is_some())
+          }
+          "shorthand_property_identifier" => prop.utf8_text(source).is_ok_and(|name| {
+            matches!(declaration(prop, name, None, source), Some(Declaration::Const(value)) if static_value_at(value, source, depth + 1).is_some())
+          }),
+          "spread_element" | "comment" => prop.kind() == "comment" || all_static(prop).is_some(),
+          _ => false,
+        })
+        .then_some(StaticValue::Other)
+    }
+    _ => None,
+  }
+}
+
+/// Whether `callee` is bound to a `const` arrow or function expression whose body only reads its own
+/// parameters and locals, static values and other such functions. It runs once either way, so side
+/// effects don't matter, but `async` functions are left out since their body reads after the call.
+fn is_static_function(callee: Node, source: &[u8], depth: usize) -> bool {
+  if depth > MAX_DEPTH {
+    return false;
+  }
+  let Ok(name) = callee.utf8_text(source) else {
+    return false;
+  };
+  let Some(Declaration::Const(function)) = declaration(callee, name, None, source) else {
+    return false;
+  };
+  matches!(function.kind(), "arrow_function" | "function_expression" | "function")
+    && function.child(0).is_some_and(|c| c.kind() != "async")
+    && function
+      .child_by_field_name("body")
+      .is_some_and(|body| reads_no_signal(body, function, source, depth))
+}
+
+fn reads_no_signal(node: Node, function: Node, source: &[u8], depth: usize) -> bool {
+  match node.kind() {
+    "member_expression" | "subscript_expression" | "this" | "super" | "new_expression" | "await_expression"
+    | "yield_expression" | "jsx_element" | "jsx_self_closing_element" | "jsx_fragment" => false,
+    "call_expression" => {
+      let (Some(callee), Some(args)) = (node.child_by_field_name("function"), node.child_by_field_name("arguments")) else {
+        return false;
+      };
+      let Ok(name) = callee.utf8_text(source) else {
+        return false;
+      };
+      // Functions declared inside are checked as part of the body
+      let callable = callee.kind() == "identifier"
+        && match declaration(callee, name, function.parent(), source) {
+          Some(Declaration::Const(value)) => is_function_kind(value.kind()),
+          Some(Declaration::Other) => false,
+          None => is_static_function(callee, source, depth + 1),
+        };
+      callable && reads_no_signal(args, function, source, depth)
+    }
+    "identifier" | "shorthand_property_identifier" => {
+      let Ok(name) = node.utf8_text(source) else {
+        return false;
+      };
+      declaration(node, name, function.parent(), source).is_some()
+        || static_value_at(node, source, depth + 1).is_some()
+    }
+    _ => {
+      let mut cursor = node.walk();
+      let mut children = node.named_children(&mut cursor);
+      children.all(|child| reads_no_signal(child, function, source, depth))
+    }
+  }
+}
+
+/// A literal folded at compile time.
+#[derive(Debug, Clone, PartialEq)]
+enum Constant {
+  Str(String),
+  Num(f64),
+}
+
+/// Text a literal expression renders as, so it can be written in the template instead of inserted.
+/// Strings, numbers, template strings without substitutions and `+` on them are folded.
+pub(super) fn constant_text(expr: Node, source: &[u8]) -> Option<String> {
+  match fold_constant(expr, source, 0)? {
+    Constant::Str(s) => Some(s),
+    Constant::Num(n) => number_text(n),
+  }
+}
+
+fn fold_constant(expr: Node, source: &[u8], depth: usize) -> Option<Constant> {
+  if depth > MAX_DEPTH {
+    return None;
+  }
+
+  match expr.kind() {
+    "string" | "template_string" => {
+      let mut s = String::new();
+      let mut cursor = expr.walk();
+      for part in expr.named_children(&mut cursor) {
+        match part.kind() {
+          "string_fragment" => s.push_str(part.utf8_text(source).ok()?),
+          "escape_sequence" => s.push_str(&unescape(part.utf8_text(source).ok()?)?),
+          _ => return None,
+        }
+      }
+      Some(Constant::Str(s))
+    }
+    "number" => parse_number(expr.utf8_text(source).ok()?).map(Constant::Num),
+    "parenthesized_expression" => {
+      let mut cursor = expr.walk();
+      let mut inner = expr.named_children(&mut cursor).filter(|c| c.kind() != "comment");
+      match (inner.next(), inner.next()) {
+        (Some(inner), None) => fold_constant(inner, source, depth + 1),
+        _ => None,
+      }
+    }
+    "unary_expression" => {
+      let Constant::Num(n) = fold_constant(expr.child_by_field_name("argument")?, source, depth + 1)? else {
+        return None;
+      };
+      match expr.child_by_field_name("operator")?.kind() {
+        "-" => Some(Constant::Num(-n)),
+        "+" => Some(Constant::Num(n)),
+        _ => None,
+      }
+    }
+    "binary_expression" if expr.child_by_field_name("operator")?.kind() == "+" => {
+      let left = fold_constant(expr.child_by_field_name("left")?, source, depth + 1)?;
+      let right = fold_constant(expr.child_by_field_name("right")?, source, depth + 1)?;
+      match (left, right) {
+        (Constant::Num(a), Constant::Num(b)) => Some(Constant::Num(a + b)),
+        (Constant::Str(a), Constant::Str(b)) => Some(Constant::Str(a + &b)),
+        (Constant::Str(a), Constant::Num(b)) => Some(Constant::Str(a + &number_text(b)?)),
+        (Constant::Num(a), Constant::Str(b)) => Some(Constant::Str(number_text(a)? + &b)),
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Decodes a JS escape sequence, legacy octal escapes and lone surrogates aren't folded.
+fn unescape(seq: &str) -> Option<Cow<'static, str>> {
+  let body = seq.strip_prefix('\\')?;
+  let c = match body {
+    "n" => '\n',
+    "t" => '\t',
+    "r" => '\r',
+    "b" => '\u{8}',
+    "f" => '\u{c}',
+    "v" => '\u{b}',
+    "0" => '\0',
+    _ if body.starts_with(['\n', '\r', '\u{2028}', '\u{2029}']) => return Some(Cow::Borrowed("")),
+    _ => {
+      let hex = body
+        .strip_prefix("u{")
+        .and_then(|h| h.strip_suffix('}'))
+        .or_else(|| body.strip_prefix('u').filter(|h| h.len() == 4))
+        .or_else(|| body.strip_prefix('x').filter(|h| h.len() == 2));
+      match hex {
+        Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+        None => {
+          let mut chars = body.chars();
+          match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_ascii_digit() => c,
+            _ => return None,
+          }
+        }
+      }
+    }
+  };
+  Some(Cow::Owned(c.to_string()))
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+  let text = text.replace('_', "");
+  let radix = match text.get(..2) {
+    Some("0x" | "0X") => 16,
+    Some("0o" | "0O") => 8,
+    Some("0b" | "0B") => 2,
+    _ => {
+      // BigInts and legacy octals like `010`
+      if text.ends_with('n') || (text.len() > 1 && text.starts_with('0') && text.as_bytes()[1].is_ascii_digit()) {
+        return None;
+      }
+      return text.parse().ok();
+    }
+  };
+  u64::from_str_radix(&text[2..], radix).ok().map(|n| n as f64)
+}
+
+/// Formats `n` like `String(n)` does, numbers JS writes in exponent form aren't folded.
+fn number_text(n: f64) -> Option<String> {
+  if n.is_nan() {
+    Some("NaN".to_string())
+  } else if n.is_infinite() {
+    Some(if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string())
+  } else if n == 0.0 {
+    Some("0".to_string())
+  } else if (1e-6..1e21).contains(&n.abs()) {
+    Some(n.to_string())
+  } else {
+    None
+  }
+}
@@ -0,0 +1,174 @@
+use crate::error::ParserError;
+use std::{borrow::Cow, str::FromStr};
+use tree_sitter::{Node, Parser};
+
+/// How the generated code is laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStyle {
+  /// One statement per line, walked nodes are annotated with their kind.
+  #[default]
+  Default,
+  /// No annotations or extra whitespace, for production bundles.
+  Compact,
+  /// Indented, walked nodes are annotated with the source element and line.
+  Debug,
+}
+
+impl FromStr for OutputStyle {
+  type Err = ParserError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "default" => Ok(Self::Default),
+      "compact" => Ok(Self::Compact),
+      "debug" => Ok(Self::Debug),
+      _ => Err(ParserError::UnknownOutputStyle(s.to_string())),
+    }
+  }
+}
+
+/// A token of the generated code, strings, template literals, regexes and comments are a single token
+/// so their contents are never touched.
+struct Token<'a> {
+  start: usize,
+  end: usize,
+  text: &'a str,
+  is_comment: bool,
+}
+
+/// Lays out a generated template function in `style`. `indent` is the indentation of the line it's
+/// inserted in, `parser` is created on the first call and reused after. Code that doesn't parse is left
+/// as is, it could be TypeScript the grammar doesn't know, debug output says so in a comment.
+pub(super) fn format<'a>(
+  code: &'a str,
+  style: OutputStyle,
+  indent: &str,
+  parser: &mut Option<Parser>,
+) -> Result<Cow<'a, str>, ParserError> {
+  if style == OutputStyle::Default {
+    return Ok(Cow::Borrowed(code));
+  }
+  let parser = match parser {
+    Some(parser) => parser,
+    None => {
+      let mut new = Parser::new();
+      new.set_language(&tree_sitter_javascript::LANGUAGE.into())?;
+      parser.insert(new)
+    }
+  };
+  let tree = parser.parse(code, None).ok_or(ParserError::Parse)?;
+  if tree.root_node().has_error() {
+    return Ok(match style {
+      OutputStyle::Debug => Cow::Owned(format!("/* Not laid out, this code couldn't be parsed */ {code}")),
+      _ => Cow::Borrowed(code),
+    });
+  }
+
+  let mut tokens = Vec::new();
+  collect_tokens(tree.root_node(), code, &mut tokens);
+  let gap = |i: usize| match i {
+    0 => "",
+    _ => &code[tokens[i - 1].end..tokens[i].start],
+  };
+
+  let mut ret = String::with_capacity(code.len());
+  match style {
+    OutputStyle::Default => unreachable!(),
+    OutputStyle::Compact => {
+      for (i, token) in tokens.iter().enumerate() {
+        let gap = gap(i);
+        if let Some(prev) = i.checked_sub(1).map(|i| &tokens[i]) {
+          let keeps_line = prev.is_comment || !matches!(prev.text, ";" | "{" | "," | "(" | "[");
+          if gap.contains('\n') && keeps_line {
+            ret.push('\n');
+          } else if !gap.is_empty() && needs_space(prev.text, token.text) {
+            ret.push(' ');
+          }
+        }
+        ret.push_str(token.text);
+      }
+    }
+    OutputStyle::Debug => {
+      let levels = line_levels(&tokens, gap);
+      let mut line = 0;
+      for (i, token) in tokens.iter().enumerate() {
+        let gap = gap(i);
+        match gap.matches('\n').count() {
+          0 => ret.push_str(gap),
+          lines => {
+            line += 1;
+            ret.push_str(&"\n".repeat(lines.min(2)));
+            ret.push_str(indent);
+            ret.push_str(&"  ".repeat(levels[line]));
+          }
+        }
+        ret.push_str(token.text);
+      }
+    }
+  }
+
+  Ok(Cow::Owned(ret))
+}
+
+/// Indentation level of each line, the number of lines with brackets still open. Lines closing
+/// brackets are indented like the line that opened them.
+fn line_levels<'a>(tokens: &[Token], gap: impl Fn(usize) -> &'a str) -> Vec<usize> {
+  let level = |open: &[usize]| {
+    let mut lines = open.to_vec();
+    lines.dedup();
+    lines.len()
+  };
+
+  let mut levels = vec![0];
+  let mut open = Vec::new();
+  let mut line = 0;
+  for (i, token) in tokens.iter().enumerate() {
+    if gap(i).contains('\n') {
+      line += 1;
+      levels.push(level(&open));
+    }
+    match token.text {
+      "{" | "(" | "[" => open.push(line),
+      "}" | ")" | "]" => {
+        open.pop();
+        levels[line] = levels[line].min(level(&open));
+      }
+      _ => (),
+    }
+  }
+  levels
+}
+
+fn collect_tokens<'a>(node: Node, code: &'a str, tokens: &mut Vec<Token<'a>>) {
+  let is_atomic = matches!(node.kind(), "string" | "template_string" | "regex" | "comment");
+  if is_atomic || node.child_count() == 0 {
+    if node.start_byte() < node.end_byte() {
+      tokens.push(Token {
+        start: node.start_byte(),
+        end: node.end_byte(),
+        text: &code[node.start_byte()..node.end_byte()],
+        is_comment: node.kind() == "comment",
+      });
+    }
+    return;
+  }
+
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    collect_tokens(child, code, tokens);
+  }
+}
+
+/// Whether dropping the space between two tokens would merge them, like `return x`, `a + +b`, `1 .x`,
+/// `a < !b` or `a-- > b`, where `-->` would start an HTML-like comment.
+fn needs_space(prev: &str, next: &str) -> bool {
+  let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii();
+  match (prev.chars().next_back(), next.chars().next()) {
+    (Some(a), Some(b)) => {
+      (is_word(a) && is_word(b)) || (a == b && matches!(a, '+' | '-')) || a == '/' || b == '/' || (a.is_ascii_digit() && b == '.')
+        || (a == '<' && b == '!')
+        || (prev.ends_with("--") && b == '>')
+    }
+    _ => false,
+  }
+}
@@ -1,8 +1,14 @@
-use super::{html_entities::parse_html_escape_sequence, Child, VAR_PREF};
+use super::{
+  escape::{escape, Context},
+  html_entities::parse_html_escape_sequence,
+  style::OutputStyle,
+  walker::{Walk, WalkStats},
+  Child, Prop, CONSTANT_KIND, VAR_PREF,
+};
 use crate::{error::ParserError, jsx_parser::JsxTemplate};
 use core::str;
-use std::{borrow::Cow, collections::HashSet, fmt::Write, ops::Range};
-use tree_sitter::Node;
+use std::{borrow::Cow, collections::{BTreeMap, BTreeSet, HashSet}, fmt::Write, ops::Range};
+use tree_sitter::{Node, Parser, Tree};
 
 pub(super) fn is_reactive_kind(kind: &str) -> bool {
   matches!(
@@ -45,10 +51,254 @@ pub(super) fn is_void_element(tag: &str) -> bool {
   )
 }
 
+/// Elements that only exist in SVG, tags shared with HTML like `a` or `title` are left out.
+pub(super) fn is_svg_element(tag: &str) -> bool {
+  matches!(
+    tag,
+    "animate"
+      | "animateMotion"
+      | "animateTransform"
+      | "circle"
+      | "clipPath"
+      | "defs"
+      | "desc"
+      | "ellipse"
+      | "feBlend"
+      | "feColorMatrix"
+      | "feComponentTransfer"
+      | "feComposite"
+      | "feConvolveMatrix"
+      | "feDiffuseLighting"
+      | "feDisplacementMap"
+      | "feDistantLight"
+      | "feDropShadow"
+      | "feFlood"
+      | "feFuncA"
+      | "feFuncB"
+      | "feFuncG"
+      | "feFuncR"
+      | "feGaussianBlur"
+      | "feImage"
+      | "feMerge"
+      | "feMergeNode"
+      | "feMorphology"
+      | "feOffset"
+      | "fePointLight"
+      | "feSpecularLighting"
+      | "feSpotLight"
+      | "feTile"
+      | "feTurbulence"
+      | "filter"
+      | "foreignObject"
+      | "g"
+      | "image"
+      | "line"
+      | "linearGradient"
+      | "marker"
+      | "mask"
+      | "metadata"
+      | "mpath"
+      | "path"
+      | "pattern"
+      | "polygon"
+      | "polyline"
+      | "radialGradient"
+      | "rect"
+      | "set"
+      | "stop"
+      | "switch"
+      | "symbol"
+      | "text"
+      | "textPath"
+      | "tspan"
+      | "use"
+      | "view"
+  )
+}
+
+pub(super) fn is_mathml_element(tag: &str) -> bool {
+  matches!(
+    tag,
+    "annotation"
+      | "annotation-xml"
+      | "maction"
+      | "menclose"
+      | "merror"
+      | "mfrac"
+      | "mi"
+      | "mmultiscripts"
+      | "mn"
+      | "mo"
+      | "mover"
+      | "mpadded"
+      | "mphantom"
+      | "mprescripts"
+      | "mroot"
+      | "mrow"
+      | "ms"
+      | "mspace"
+      | "msqrt"
+      | "mstyle"
+      | "msub"
+      | "msubsup"
+      | "msup"
+      | "mtable"
+      | "mtd"
+      | "mtext"
+      | "mtr"
+      | "munder"
+      | "munderover"
+      | "semantics"
+  )
+}
+
+/// Elements whose text is kept verbatim. Browsers render `pre` and `listing` with `white-space: pre` and
+/// `textarea` with `pre-wrap`, its text being its value.
+fn is_preformatted_element(tag: &str) -> bool {
+  matches!(tag, "pre" | "textarea" | "listing")
+}
+
+/// `white-space` values that keep every space, `pre-line` still collapses them.
+fn is_preserving_white_space(value: &str) -> bool {
+  let value = value.trim().trim_end_matches("!important").trim_end();
+  ["pre", "pre-wrap", "break-spaces"].iter().any(|v| value.eq_ignore_ascii_case(v))
+}
+
+/// Whether JSX text inside `node` has to be kept verbatim instead of folded, that's the case inside
+/// preformatted elements, `white-space: pre*` containers and elements marked with `$preserveWhitespace`.
+/// Only static styles are looked at, a reactive `$style` or a class setting `white-space` can't be seen.
+pub(super) fn preserves_whitespace(node: Node, source: &[u8]) -> bool {
+  let mut current = Some(node);
+  while let Some(n) = current {
+    current = n.parent();
+
+    let opening = match n.kind() {
+      "jsx_element" => n.child_by_field_name("open_tag"),
+      "jsx_self_closing_element" => Some(n),
+      _ => None,
+    };
+    let Some(opening) = opening
+    else {
+      continue;
+    };
+
+    if opening
+      .child_by_field_name("name")
+      .is_some_and(|name| name.utf8_text(source).is_ok_and(is_preformatted_element))
+    {
+      return true;
+    }
+
+    let mut cursor = opening.walk();
+    for attr in opening.children_by_field_name("attribute", &mut cursor) {
+      let (Some(key), value) = (attr.named_child(0), attr.named_child(1))
+      else {
+        continue;
+      };
+      let value = value
+        .and_then(|v| v.utf8_text(source).ok())
+        .map(|v| v.trim_matches(['"', '\'', '{', '}', ' ']));
+
+      match key.utf8_text(source).unwrap_or_default() {
+        "$preserveWhitespace" if value.is_none_or(|v| v == "true") => return true,
+        "style:white-space" if value.is_some_and(is_preserving_white_space) => return true,
+        "style"
+          if value.is_some_and(|style| {
+            style.split(';').any(|declaration| {
+              declaration
+                .split_once(':')
+                .is_some_and(|(property, value)| property.trim() == "white-space" && is_preserving_white_space(value))
+            })
+          }) =>
+        {
+          return true;
+        }
+        _ => (),
+      }
+    }
+  }
+
+  false
+}
+
+/// Attribute prefixes that must be set with `setAttributeNS`, the runtime resolves the namespace URI.
+pub(super) fn is_namespaced_attribute(key: &str) -> bool {
+  let key = key.strip_prefix('$').unwrap_or(key);
+  key.starts_with("xlink:") || key.starts_with("xml:") || key.starts_with("xmlns:")
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Namespace {
+  #[default]
+  Html,
+  Svg,
+  MathMl,
+}
+
+impl Namespace {
+  /// Namespace an element is created in, following its JSX ancestors up to the closest `<svg>`,
+  /// `<math>` or `<foreignObject>`. Elements without one (component roots, elements nested in
+  /// components) are guessed from their tag.
+  pub(super) fn of(node: Node, tag: &str, source: &[u8]) -> Self {
+    match tag {
+      "svg" => return Self::Svg,
+      "math" => return Self::MathMl,
+      _ => (),
+    }
+
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+      parent = p.parent();
+      if !is_jsx_element(p.kind()) {
+        continue;
+      }
+
+      let name = if p.kind() == "jsx_element" {
+        p.child_by_field_name("open_tag").and_then(|t| t.child_by_field_name("name"))
+      }
+      else {
+        p.child_by_field_name("name")
+      };
+
+      // Fragments don't affect the namespace
+      let Some(name) = name
+      else {
+        continue;
+      };
+
+      let parent_tag = name.utf8_text(source).unwrap_or_default();
+      if name.kind() != "identifier" || parent_tag.starts_with(|c: char| c.is_ascii_uppercase()) {
+        break;
+      }
+
+      return if parent_tag == "foreignObject" { Self::Html } else { Self::of(p, parent_tag, source) };
+    }
+
+    if is_svg_element(tag) {
+      Self::Svg
+    }
+    else if is_mathml_element(tag) {
+      Self::MathMl
+    }
+    else {
+      Self::Html
+    }
+  }
+
+  /// Root tag that makes `innerHTML` parse its children in this namespace.
+  pub(super) fn root_tag(self) -> Option<&'static str> {
+    match self {
+      Self::Html => None,
+      Self::Svg => Some("svg"),
+      Self::MathMl => Some("math"),
+    }
+  }
+}
+
 pub(super) fn is_static_kind(kind: &str) -> bool {
   matches!(
     kind,
-    "string_fragment" | "number" | "property_identifier" | "jsx_namespace_name" | "false" | "true"
+    "string_fragment" | "number" | "property_identifier" | "jsx_namespace_name" | "false" | "true" | CONSTANT_KIND
   )
 }
 
@@ -61,29 +311,113 @@ pub(super) fn wrap_reactive_value<'a>(kind: &str, value: &'a str) -> Cow<'a, str
   }
 }
 
+/// JS literal for a prop whose value is known at compile time.
+pub(super) fn static_prop_value<'a>(prop: &Prop<'a>) -> Cow<'a, str> {
+  match (prop.kind, prop.value) {
+    ("string_fragment", Some(v)) => Cow::Owned(format!("\"{}\"", escape(v, Context::JsString))),
+    (_, Some(v)) => Cow::Borrowed(v),
+    (_, None) => Cow::Borrowed("true"),
+  }
+}
+
+/// Code generation settings that stay the same for every file.
+#[derive(Debug, Default)]
+pub struct GenOptions {
+  /// Set non-static attributes of custom elements (tags with a dash) as DOM properties.
+  pub custom_element_props: bool,
+  /// Handle bubbling `on:` events with one document listener per event type.
+  pub delegate_events: bool,
+  pub output_style: OutputStyle,
+}
+
 #[derive(Default)]
 pub struct GlobalState {
   pub(super) import_path: Cow<'static, str>,
+  pub(super) options: GenOptions,
   pub(super) events: HashSet<Box<str>>,
+  pub(super) delegated_events: BTreeSet<Box<str>>,
   pub(super) imports: HashSet<&'static str>,
-  pub(super) templates: HashSet<usize>,
+  /// `template()` calls by variable name, elements with identical HTML share one.
+  pub(super) templates: BTreeMap<Box<str>, String>,
+  /// Import path of the shared templates module from the file being compiled.
+  templates_import: Option<String>,
+  /// Every template declared in the shared module so far.
+  shared_templates: BTreeMap<Box<str>, String>,
+  /// Walk of the function being generated, each nested function gets its own.
+  pub(super) walk: Walk,
+  pub(super) walk_stats: WalkStats,
+  /// Parser laying out generated code, created on first use when the output style needs it.
+  pub(super) style_parser: Option<Parser>,
   pub(super) is_component_child: bool,
   pub(super) is_template_child: bool,
   pub(super) parsing_special_root: bool,
+  /// Set right before generating a component branch of an `$if` chain, the chain handles its `$if`.
+  pub(super) is_branch: bool,
 }
 
 impl GlobalState {
-  pub fn new(import_path: Option<String>) -> Self {
+  pub fn new(import_path: Option<String>, options: GenOptions) -> Self {
     Self {
       import_path: match import_path {
         Some(p) => Cow::Owned(p),
         None => Cow::Borrowed("jsx"),
       },
+      options,
       ..Default::default()
     }
   }
 
-  pub fn generate_setup_js(&mut self, templates: &[JsxTemplate]) -> Result<String, ParserError> {
+  /// Declares templates in a shared module imported from `path` instead of in each file,
+  /// so identical elements across files share one template.
+  pub fn set_templates_import(&mut self, path: Option<String>) {
+    self.templates_import = path;
+  }
+
+  /// Variable of the template for `html`, identical HTML in the same file (or in every file
+  /// when templates are shared) gets the same variable.
+  pub(super) fn template_var(&mut self, html: &str, root: Option<&str>) -> Result<Box<str>, ParserError> {
+    let mut call = format!("{VAR_PREF}template(`{}`", escape(html, Context::TemplateLiteral));
+    // Detached SVG/MathML elements need a wrapper to be parsed in the right namespace
+    if let Some(root) = root {
+      write!(call, ", \"{root}\"")?;
+    }
+    write!(call, ")")?;
+
+    // A hash colliding with another template, in this file or the shared module, gets the next free suffix
+    let hash = fnv1a(call.as_bytes());
+    let mut var: Box<str> = format!("{VAR_PREF}templ_{hash:016x}").into();
+    let mut suffix = 0;
+    while [self.templates.get(&var), self.shared_templates.get(&var)]
+      .into_iter()
+      .flatten()
+      .any(|stored| *stored != call)
+    {
+      suffix += 1;
+      var = format!("{VAR_PREF}templ_{hash:016x}_{suffix}").into();
+    }
+    self.templates.entry(var.clone()).or_insert(call);
+    Ok(var)
+  }
+
+  /// Contents of the shared templates module, `None` when no file used it.
+  pub fn generate_shared_templates(&mut self) -> Result<Option<String>, ParserError> {
+    if self.shared_templates.is_empty() {
+      return Ok(None);
+    }
+
+    let mut module = String::new();
+    writeln!(module, "import {{ template as {VAR_PREF}template }} from \"{}\";\n", self.import_path)?;
+    for (var, call) in std::mem::take(&mut self.shared_templates) {
+      writeln!(module, "export const {var} = {call};")?;
+    }
+    Ok(Some(module))
+  }
+
+  pub fn generate_setup_js(&mut self) -> Result<String, ParserError> {
+    if self.templates_import.is_some() {
+      self.imports.remove("template");
+    }
+
     let mut setup = String::with_capacity(self.imports.len() * 128);
     for import in &self.imports {
       writeln!(setup, "import {{ {import} as {VAR_PREF}{import} }} from \"{}\";", self.import_path)?;
@@ -94,15 +428,27 @@ impl GlobalState {
         )?;
       }
     }
-    writeln!(setup)?;
+    if self.options.output_style != OutputStyle::Compact {
+      writeln!(setup)?;
+    }
     self.imports.clear();
 
-    for templ_id in &self.templates {
-      let templ = templates[*templ_id].generate_template_string(templates)?;
-      writeln!(setup, "const {VAR_PREF}templ{} = {VAR_PREF}template(`{templ}`);", templ_id)?;
+    let templates = std::mem::take(&mut self.templates);
+    match &self.templates_import {
+      Some(path) if !templates.is_empty() => {
+        let vars = templates.keys().map(|var| &**var).collect::<Vec<_>>();
+        writeln!(setup, "import {{ {} }} from \"{path}\";", vars.join(", "))?;
+        self.shared_templates.extend(templates);
+      }
+      _ => {
+        for (var, call) in templates {
+          writeln!(setup, "const {var} = {call};")?;
+        }
+      }
+    }
+    if self.options.output_style != OutputStyle::Compact {
+      writeln!(setup)?;
     }
-    writeln!(setup)?;
-    self.templates.clear();
 
     for event in &self.events {
       let var = generate_event_var(event);
@@ -110,15 +456,479 @@ impl GlobalState {
     }
     self.events.clear();
 
+    for event in &self.delegated_events {
+      writeln!(setup, "{VAR_PREF}delegateEvent(\"{event}\");")?;
+    }
+    self.delegated_events.clear();
+
     Ok(setup)
   }
 }
 
+/// FNV-1a, stable across runs so template names don't change between builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes
+    .iter()
+    .fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
 pub(super) fn generate_event_var(event_name: &str) -> String {
   format!("{VAR_PREF}global_event_{event_name}")
 }
 
-pub(super) fn merge_jsx_text(children: &[Child], idx: &mut usize, escape: bool) -> Result<String, ParserError> {
+/// `x` in `$static(x)`, which marks a value to be read once instead of tracked.
+pub(super) fn unwrap_static_call<'a>(node: Node<'a>, source: &[u8]) -> Result<Option<Node<'a>>, ParserError> {
+  if node.kind() != "call_expression"
+    || node
+      .child_by_field_name("function")
+      .map(|f| f.utf8_text(source))
+      .transpose()?
+      != Some("$static")
+  {
+    return Ok(None);
+  }
+
+  let args = node.child_by_field_name("arguments").ok_or(ParserError::Parse)?;
+  match args.named_child_count() {
+    1 => Ok(args.named_child(0)),
+    _ => Err(ParserError::msg("\"$static()\" takes a single expression", node)),
+  }
+}
+
+/// `$static` only exists at compile time, anywhere but around the whole value of a `{}` it would be left
+/// as a call to an undefined function.
+pub(super) fn check_static_references(node: Node, source: &[u8]) -> Result<(), ParserError> {
+  if node.kind() == "identifier" && node.utf8_text(source)? == "$static" {
+    let call = node
+      .parent()
+      .filter(|p| p.kind() == "call_expression" && p.child_by_field_name("function") == Some(node));
+    if call.and_then(|c| c.parent()).is_none_or(|p| p.kind() != "jsx_expression") {
+      return Err(ParserError::msg(
+        "\"$static()\" must wrap the whole value of an attribute, prop or child",
+        node,
+      ));
+    }
+  }
+
+  let mut cursor = node.walk();
+  for child in node.named_children(&mut cursor) {
+    check_static_references(child, source)?;
+  }
+  Ok(())
+}
+
+/// `/* @once */` before an expression marks it to be read once instead of tracked.
+pub(super) fn is_once_comment(node: Node, source: &[u8]) -> Result<bool, ParserError> {
+  Ok(node.kind() == "comment"
+    && node
+      .utf8_text(source)?
+      .strip_prefix("/*")
+      .and_then(|c| c.strip_suffix("*/"))
+      .is_some_and(|c| c.trim() == "@once"))
+}
+
+/// Whether an attribute value is preceded by a `/* @once */` comment inside its braces.
+pub(super) fn follows_once_comment(node: Node, source: &[u8]) -> Result<bool, ParserError> {
+  let mut prev = node.prev_named_sibling();
+  while let Some(comment) = prev.filter(|p| p.kind() == "comment") {
+    if is_once_comment(comment, source)? {
+      return Ok(true);
+    }
+    prev = comment.prev_named_sibling();
+  }
+
+  Ok(false)
+}
+
+/// Expression inside a `{}` child and whether it's marked untracked with `/* @once */` or `$static()`.
+/// Comment-only children keep the comment as their expression.
+pub(super) fn untracked_expression<'a>(
+  jsx_expression: Node<'a>,
+  source: &[u8],
+) -> Result<(Option<Node<'a>>, bool), ParserError> {
+  let mut once = false;
+  let mut expr = None;
+  let mut cursor = jsx_expression.walk();
+  for child in jsx_expression.named_children(&mut cursor) {
+    if child.kind() != "comment" {
+      expr = Some(child);
+      break;
+    }
+    once |= is_once_comment(child, source)?;
+  }
+
+  let Some(expr) = expr else {
+    return Ok((jsx_expression.named_child(0), false));
+  };
+  Ok(match unwrap_static_call(expr, source)? {
+    Some(inner) => (Some(inner), true),
+    None => (Some(expr), once),
+  })
+}
+
+/// Applies edits sorted by position, they must not overlap.
+pub(super) fn apply_edits(source: &[u8], edits: &[SourceEdit]) -> Option<Vec<u8>> {
+  let mut out = Vec::with_capacity(source.len() + edits.iter().map(|e| e.text.len()).sum::<usize>());
+  let mut src_idx = 0;
+  for edit in edits {
+    if edit.start < src_idx {
+      return None;
+    }
+    out.extend_from_slice(&source[src_idx..edit.start]);
+    out.extend_from_slice(edit.text.as_bytes());
+    src_idx = edit.end;
+  }
+  out.extend_from_slice(&source[src_idx..]);
+
+  Some(out)
+}
+
+/// How a `<slot>` reaches the slots its component was called with.
+#[derive(Debug, Clone)]
+pub(super) struct SlotsParam<'a> {
+  /// Name of the slots parameter in the enclosing component function.
+  pub(super) name: Cow<'a, str>,
+  /// Source edit declaring the parameter when the function doesn't have one yet.
+  pub(super) edit: Option<SourceEdit>,
+}
+
+/// Replaces `start..end` in the source with `text`, empty ranges insert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SourceEdit {
+  pub(super) start: usize,
+  pub(super) end: usize,
+  pub(super) text: String,
+}
+
+pub(super) fn is_function_kind(kind: &str) -> bool {
+  matches!(
+    kind,
+    "function_declaration"
+      | "generator_function_declaration"
+      | "function_expression"
+      | "function"
+      | "generator_function"
+      | "arrow_function"
+      | "method_definition"
+  )
+}
+
+/// Functions with a capitalized name, or assigned to a capitalized variable, are components.
+pub(super) fn is_component_function(node: Node, source: &[u8]) -> bool {
+  let name = node.child_by_field_name("name").or_else(|| {
+    node
+      .parent()
+      .filter(|p| p.kind() == "variable_declarator")
+      .and_then(|p| p.child_by_field_name("name"))
+  });
+
+  name
+    .and_then(|n| n.utf8_text(source).ok())
+    .is_some_and(|n| n.starts_with(|c: char| c.is_ascii_uppercase()))
+}
+
+/// Finds the component function enclosing a `<slot>` and its slots (second) parameter.
+/// The nearest capitalized function is the component, so callbacks such as
+/// `items.map(() => <slot />)` still resolve to it.
+pub(super) fn slots_param<'a>(slot: Node<'a>, source: &'a [u8]) -> Result<SlotsParam<'a>, ParserError> {
+  let mut function = None;
+  let mut parent = slot.parent();
+  while let Some(node) = parent {
+    if is_function_kind(node.kind()) && is_component_function(node, source) {
+      function = Some(node);
+      break;
+    }
+    parent = node.parent();
+  }
+
+  // Functions declared inside JSX are rewritten along with their template
+  let inside_jsx = |mut node: Node| {
+    while let Some(parent) = node.parent() {
+      if is_jsx_element(parent.kind()) {
+        return true;
+      }
+      node = parent;
+    }
+    false
+  };
+  let Some(function) = function.filter(|f| !inside_jsx(*f)) else {
+    return Err(ParserError::msg("<slot> must be used inside a component function", slot));
+  };
+  let slots = format!("{VAR_PREF}slots");
+
+  // `props => ...`
+  if let Some(param) = function.child_by_field_name("parameter") {
+    return Ok(SlotsParam {
+      edit: Some(SourceEdit {
+        start: param.start_byte(),
+        end: param.end_byte(),
+        text: format!("({}, {slots})", param.utf8_text(source)?),
+      }),
+      name: Cow::Owned(slots),
+    });
+  }
+
+  let params = function
+    .child_by_field_name("parameters")
+    .ok_or_else(|| ParserError::msg("<slot> must be used inside a component function", slot))?;
+  let mut cursor = params.walk();
+  let named = params
+    .named_children(&mut cursor)
+    .filter(|n| n.kind() != "comment")
+    .collect::<Vec<_>>();
+
+  match named.as_slice() {
+    [] => Ok(SlotsParam {
+      edit: Some(SourceEdit {
+        start: params.start_byte() + 1,
+        end: params.start_byte() + 1,
+        text: format!("{VAR_PREF}props, {slots}"),
+      }),
+      name: Cow::Owned(slots),
+    }),
+    [props] if props.kind() != "rest_pattern" => Ok(SlotsParam {
+      edit: Some(SourceEdit {
+        start: props.end_byte(),
+        end: props.end_byte(),
+        text: format!(", {slots}"),
+      }),
+      name: Cow::Owned(slots),
+    }),
+    [_, param, ..] => {
+      let param = match param.kind() {
+        "assignment_pattern" => param.child_by_field_name("left"),
+        _ => Some(*param),
+      };
+      match param {
+        Some(param) if param.kind() == "identifier" => Ok(SlotsParam {
+          name: Cow::Borrowed(param.utf8_text(source)?),
+          edit: None,
+        }),
+        _ => Err(ParserError::msg(
+          "<slot> needs the component's second parameter to be a plain identifier",
+          slot,
+        )),
+      }
+    }
+    _ => Err(ParserError::msg(
+      "<slot> needs the component's second parameter to be a plain identifier",
+      slot,
+    )),
+  }
+}
+
+/// Events that bubble up to the document, only these can be delegated. Touch events are left out,
+/// browsers make their document listeners passive so they couldn't prevent scrolling.
+pub(super) fn is_delegatable_event(event_name: &str) -> bool {
+  matches!(
+    event_name,
+    "click"
+      | "dblclick"
+      | "auxclick"
+      | "contextmenu"
+      | "input"
+      | "beforeinput"
+      | "change"
+      | "keydown"
+      | "keyup"
+      | "focusin"
+      | "focusout"
+      | "mousedown"
+      | "mouseup"
+      | "mousemove"
+      | "mouseover"
+      | "mouseout"
+      | "pointerdown"
+      | "pointerup"
+      | "pointermove"
+      | "pointerover"
+      | "pointerout"
+  )
+}
+
+/// Stands in for `|` in `on:click|prevent` keys, the grammar doesn't allow `|` in attribute
+/// names but `$` is valid and has the same length so every node keeps its position. Event names
+/// can't contain it themselves.
+pub(super) const MODIFIER_SEPARATOR: u8 = b'$';
+
+/// Replaces the `|` of event modifiers (`on:click|prevent={...}`, `g:onscroll|passive={...}`)
+/// with [`MODIFIER_SEPARATOR`], borrows when there are none. Only the `|` right after the name of
+/// an `on:`/`g:on` JSX attribute in `tree` are replaced, `tree` is re-parsed after each round since
+/// error recovery can hide the attributes after a `|`.
+pub(super) fn mask_event_modifiers<'a>(
+  parser: &mut Parser,
+  source: &'a [u8],
+  tree: Tree,
+) -> Result<(Cow<'a, [u8]>, Tree), ParserError> {
+  let mut source = Cow::Borrowed(source);
+  let mut tree = tree;
+  let mut masked = Vec::new();
+  loop {
+    let mut found = Vec::new();
+    modifier_separators(tree.root_node(), &source, &masked, &mut found)?;
+    if found.is_empty() {
+      return Ok((source, tree));
+    }
+
+    let rewritten = source.to_mut();
+    for &sep in &found {
+      rewritten[sep] = MODIFIER_SEPARATOR;
+    }
+    masked.extend(found);
+    tree = parser.parse(&*source, None).ok_or(ParserError::Parse)?;
+  }
+}
+
+/// Positions of the `|` following event attribute names in `node`, `masked` are the ones replaced
+/// in earlier rounds.
+fn modifier_separators(node: Node, source: &[u8], masked: &[usize], found: &mut Vec<usize>) -> Result<(), ParserError> {
+  if node.kind() == "jsx_attribute"
+    && let Some(name) = node.named_child(0).filter(|n| n.kind() == "jsx_namespace_name")
+    && let text = name.utf8_text(source)?
+    && (text.starts_with("on:") || text.starts_with("g:on"))
+  {
+    let start = name.start_byte();
+    if text.bytes().enumerate().any(|(i, c)| c == MODIFIER_SEPARATOR && !masked.contains(&(start + i))) {
+      return Err(ParserError::msg("Event names can't contain \"$\"", name));
+    }
+
+    let is_name = |c: &u8| c.is_ascii_alphanumeric() || *c == b'_' || *c == b'-';
+    let mut end = name.end_byte();
+    let mut separators = Vec::new();
+    while source.get(end) == Some(&b'|') {
+      separators.push(end);
+      end += 1;
+      end += source[end..].iter().take_while(|c| is_name(c)).count();
+    }
+    if source.get(end) == Some(&b'=') {
+      found.extend(separators);
+    }
+  }
+
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    modifier_separators(child, source, masked, found)?;
+  }
+  Ok(())
+}
+
+/// Modifiers written after an event name, `on:click|prevent|once`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct EventModifiers {
+  pub(super) prevent: bool,
+  pub(super) stop: bool,
+  pub(super) once: bool,
+  pub(super) passive: bool,
+  pub(super) capture: bool,
+}
+
+impl EventModifiers {
+  /// Splits `click$prevent$once` into the event name and its modifiers.
+  pub(super) fn parse<'a>(event: &'a str, node: Node) -> Result<(&'a str, Self), ParserError> {
+    let mut parts = event.split(MODIFIER_SEPARATOR as char);
+    let name = parts.next().unwrap_or_default();
+    let mut ret = Self::default();
+
+    for modifier in parts {
+      match modifier {
+        "prevent" => ret.prevent = true,
+        "stop" => ret.stop = true,
+        "once" => ret.once = true,
+        "passive" => ret.passive = true,
+        "capture" => ret.capture = true,
+        _ => {
+          return Err(ParserError::msg(
+            "Unknown event modifier, expected one of prevent, stop, once, passive or capture",
+            node,
+          ))
+        }
+      }
+    }
+
+    if ret.passive && ret.prevent {
+      return Err(ParserError::msg("Passive listeners can't use the prevent modifier", node));
+    }
+
+    Ok((name, ret))
+  }
+
+  /// Wraps `handler` so it calls `preventDefault`/`stopPropagation` before running.
+  pub(super) fn wrap_handler<'a>(&self, handler: &'a str) -> Result<Cow<'a, str>, ParserError> {
+    if !self.prevent && !self.stop {
+      return Ok(Cow::Borrowed(handler));
+    }
+
+    let mut s = format!("(({VAR_PREF}fn) => function ({VAR_PREF}e) {{ ");
+    if self.prevent {
+      write!(s, "{VAR_PREF}e.preventDefault(); ")?;
+    }
+    if self.stop {
+      write!(s, "{VAR_PREF}e.stopPropagation(); ")?;
+    }
+    write!(s, "return {VAR_PREF}fn.call(this, {VAR_PREF}e); }})({handler})")?;
+    Ok(Cow::Owned(s))
+  }
+
+  /// `addEventListener` options, `None` when the defaults are fine.
+  pub(super) fn listener_options(&self) -> Option<String> {
+    let options = [("once", self.once), ("passive", self.passive), ("capture", self.capture)]
+      .into_iter()
+      .filter(|(_, set)| *set)
+      .map(|(name, _)| format!("{name}: true"))
+      .collect::<Vec<_>>();
+
+    (!options.is_empty()).then(|| format!("{{ {} }}", options.join(", ")))
+  }
+}
+
+/// Attribute value written in the template, folded constants are JS strings so their `&` is taken literally.
+pub(super) fn template_attribute_value<'a>(prop: &Prop<'a>) -> Option<Cow<'a, str>> {
+  match &prop.constant {
+    Some(constant) => Some(Cow::Owned(constant.replace('&', "&amp;"))),
+    None => prop.value.map(Cow::Borrowed),
+  }
+}
+
+/// Merges the text children starting at `idx` into one text node, folded constants are inserted as is
+/// between runs of JSX text.
+pub(super) fn merge_jsx_text(
+  children: &[Child],
+  idx: &mut usize,
+  escape: bool,
+  preserve_whitespace: bool,
+) -> Result<String, ParserError> {
+  let surround = if escape { "\"" } else { "" };
+  let mut text = String::from(surround);
+
+  while let Some(child) = children.get(*idx) {
+    if let Some(constant) = &child.constant {
+      match escape {
+        true => write!(text, "{}", super::escape::escape(constant, Context::JsString))?,
+        false => write!(text, "{}", constant.replace('&', "&amp;"))?,
+      }
+      *idx += 1;
+    } else if is_jsx_text(child.kind) {
+      let run = merge_text_run(children, idx, escape, preserve_whitespace)?;
+      write!(text, "{}", &run[surround.len()..run.len() - surround.len()])?;
+    } else {
+      break;
+    }
+  }
+  write!(text, "{surround}")?;
+
+  Ok(text)
+}
+
+fn is_text_run(kind: &str) -> bool {
+  matches!(kind, "jsx_text" | "html_character_reference")
+}
+
+fn merge_text_run(
+  children: &[Child],
+  idx: &mut usize,
+  escape: bool,
+  preserve_whitespace: bool,
+) -> Result<String, ParserError> {
   let offset;
   let surround;
   if escape {
@@ -132,12 +942,27 @@ pub(super) fn merge_jsx_text(children: &[Child], idx: &mut usize, escape: bool)
 
   let mut text = String::from(surround);
 
+  if preserve_whitespace {
+    while let Some(child) = children.get(*idx) {
+      match (child.kind, escape) {
+        ("jsx_text", true) => write!(text, "{}", super::escape::escape(child.value, Context::JsString))?,
+        ("html_character_reference", true) => parse_html_escape_sequence(child.value, &mut text)?,
+        ("jsx_text" | "html_character_reference", false) => write!(text, "{}", child.value)?,
+        _ => break,
+      }
+      *idx += 1;
+    }
+    write!(text, "{surround}")?;
+    return Ok(text);
+  }
+
   let prev_child = (*idx > 0).then(|| children.get(*idx - 1)).flatten();
   if escape {
     while let Some(child) = children.get(*idx) {
       match child.kind {
         "jsx_text" => {
-          write!(text, "{}", child.value.replace('"', r#"\""#))?;
+          // Line terminators get folded into spaces below, only quotes and backslashes can break out
+          write!(text, "{}", child.value.replace('\\', r"\\").replace('"', r#"\""#))?;
         }
         "html_character_reference" => {
           parse_html_escape_sequence(child.value, &mut text)?;
@@ -150,7 +975,7 @@ pub(super) fn merge_jsx_text(children: &[Child], idx: &mut usize, escape: bool)
   else {
     while let Some(child) = children.get(*idx) {
       if matches!(child.kind, "jsx_text" | "html_character_reference") {
-        write!(text, "{}", child.value.replace('"', r#"\""#))?;
+        write!(text, "{}", child.value)?;
       }
       else {
         break;
@@ -163,14 +988,14 @@ pub(super) fn merge_jsx_text(children: &[Child], idx: &mut usize, escape: bool)
   let bytes = &text.as_bytes()[offset..];
   let mut append_space = false;
   {
-    let start = if (prev_child.is_none() || prev_child.is_some_and(|c| !is_jsx_text(c.kind))) && bytes.first().is_some_and(|b| *b == b' ') {
+    let start = if (prev_child.is_none() || prev_child.is_some_and(|c| !is_text_run(c.kind))) && bytes.first().is_some_and(|b| *b == b' ') {
       offset + 1
     }
     else {
       offset
     };
 
-    if (next_child.is_none() || next_child.is_some_and(|c| !is_jsx_text(c.kind))) && children[*idx - 1].kind == "jsx_text" {
+    if (next_child.is_none() || next_child.is_some_and(|c| !is_text_run(c.kind))) && children[*idx - 1].kind == "jsx_text" {
       append_space = bytes
         .iter()
         .rposition(|b| !b.is_ascii_whitespace())
@@ -320,8 +1145,9 @@ pub(super) fn fold_whitespace(range: Option<Range<usize>>, s: &mut String) {
   s.truncate(len);
 }
 
+/// Children rendered as text, folded constants included.
 pub(super) fn is_jsx_text(kind: &str) -> bool {
-  matches!(kind, "jsx_text" | "html_character_reference")
+  matches!(kind, "jsx_text" | "html_character_reference" | CONSTANT_KIND)
 }
 
 pub(super) fn is_jsx_element(kind: &str) -> bool {
@@ -1,6 +1,41 @@
 #[cfg(test)]
 mod tests {
-  use super::super::utils::fold_whitespace;
+  use super::super::utils::{fold_whitespace, mask_event_modifiers, merge_jsx_text};
+  use std::borrow::Cow;
+  use crate::jsx_parser::{JsParser, JsxTemplate, ParserError, Q_JSX_TEMPLATE};
+
+  /// Merges the text children of the innermost element in `source`, like templates do.
+  fn merge_text(source: &str, escape: bool) -> String {
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let tree = parser.tree(source.as_bytes()).expect("Tree should parse");
+    let matches = parser
+      .parse(tree.root_node(), source.as_bytes())
+      .expect("Tree root node should parse");
+    let template = matches
+      .map(|m| JsxTemplate::parse(m.captures, source.as_bytes()).expect("Template should parse"))
+      .next()
+      .expect("Source should have a template");
+
+    let mut idx = 0;
+    merge_jsx_text(&template.children, &mut idx, escape, template.preserve_whitespace).expect("Text should merge")
+  }
+
+  /// Inputs from the folding tests above, none of these should be folded in whitespace sensitive elements.
+  const FOLD_INPUTS: &[&str] = &[
+    "   Hello    World   ",
+    "Hello\n\n\nWorld",
+    "Hello\t\t  \tWorld\t\t!",
+    " \t Hello   \n World\t  \r\n !\t ok \n ",
+    "   \n\n   Hello World   \n\n   ",
+    "     \t\n    \r\n  ",
+    "Hello     World",
+    "Hello   ,   World   !   ",
+    "   Hello\u{00A0}World",
+    "\n\n\t\t   Hello\n\n\t  World\t \t\n",
+    "This    is     a    test.     Fold    it     well.",
+    "   こんにちは   世界   ",
+    "1  2  3    4    5",
+  ];
 
   #[test]
   fn test_fold_basic_whitespace() {
@@ -127,4 +162,118 @@ mod tests {
     fold_whitespace(None, &mut input);
     assert_eq!(input, "1 2 3 4 5", "Output: {input:?}");
   }
+
+  #[test]
+  fn test_preformatted_elements_keep_whitespace() {
+    for tag in ["pre", "textarea", "listing"] {
+      for input in FOLD_INPUTS {
+        let text = merge_text(&format!("<{tag}>{input}</{tag}>"), false);
+        assert_eq!(text, *input, "Tag: {tag}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_nested_in_preformatted_elements_keep_whitespace() {
+    for input in FOLD_INPUTS {
+      let text = merge_text(&format!("<pre><b>{input}</b></pre>"), false);
+      assert_eq!(text, *input);
+    }
+  }
+
+  #[test]
+  fn test_opt_in_attributes_keep_whitespace() {
+    for (open, close) in [
+      ("<x-editor $preserveWhitespace>", "</x-editor>"),
+      ("<x-editor $preserveWhitespace={true}>", "</x-editor>"),
+      ("<div style:white-space=\"pre\">", "</div>"),
+      ("<div style:white-space=\"pre-wrap\">", "</div>"),
+      ("<div style:white-space={\"break-spaces\"}>", "</div>"),
+      ("<div style=\"color: red; white-space: pre-wrap\">", "</div>"),
+      ("<div style={\"white-space:break-spaces !important\"}>", "</div>"),
+    ] {
+      for input in FOLD_INPUTS {
+        let text = merge_text(&format!("{open}{input}{close}"), false);
+        assert_eq!(text, *input, "Element: {open}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_preserved_whitespace_is_escaped_for_js() {
+    for input in FOLD_INPUTS {
+      let text = merge_text(&format!("<pre>{input}</pre>"), true);
+      let expected = format!("\"{}\"", input.replace('\n', "\\n").replace('\r', "\\r"));
+      assert_eq!(text, expected);
+    }
+  }
+
+  #[test]
+  fn test_other_elements_still_fold() {
+    for open in [
+      "<div>",
+      "<div $preserveWhitespace={false}>",
+      "<div style:white-space=\"normal\">",
+      "<div style:white-space=\"pre-line\">",
+      "<div style=\"white-space: pre-line; color: red\">",
+      "<div style=\"--white-space: pre\">",
+    ] {
+      let text = merge_text(&format!("{open}   Hello    World   </div>"), false);
+      assert_eq!(text, " Hello World ", "Element: {open}");
+    }
+    // Browsers collapse whitespace in `code` like in any other element
+    assert_eq!(merge_text("<code>   Hello    World   </code>", false), " Hello World ");
+  }
+
+  fn mask(input: &str) -> Result<Cow<'_, [u8]>, ParserError> {
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let tree = parser.tree(input.as_bytes()).expect("Tree should parse");
+    mask_event_modifiers(&mut parser.parser, input.as_bytes(), tree).map(|(masked, _)| masked)
+  }
+
+  #[test]
+  fn test_mask_event_modifiers() {
+    for (input, expected) in [
+      ("<a on:click|prevent={f} />", "<a on:click$prevent={f} />"),
+      ("<a\n  g:onscroll|passive|once={f} />", "<a\n  g:onscroll$passive$once={f} />"),
+      ("<a on:my-event|stop=\"\" />", "<a on:my-event$stop=\"\" />"),
+      ("<a href=\"x\" on:click|prevent|stop={f} on:key|once={g} />", "<a href=\"x\" on:click$prevent$stop={f} on:key$once={g} />"),
+      ("<a title=\" on:x|y=\" on:click|once={f} />", "<a title=\" on:x|y=\" on:click$once={f} />"),
+      (
+        "<div><a on:click|stop={f} />{x || y}<b on:key|once={g} on:x|a|b={h} /></div>",
+        "<div><a on:click$stop={f} />{x || y}<b on:key$once={g} on:x$a$b={h} /></div>",
+      ),
+    ] {
+      assert_eq!(mask(input).expect("Modifiers should be masked"), expected.as_bytes(), "Input: {input}");
+    }
+
+    for input in [
+      "a || b",
+      "({ on:x|y })",
+      "x = on:a|b",
+      "<a on:click={a|b} />",
+      "<a xon:click|b={f} />",
+      "const s = \" on:click|prevent=\";",
+      "// on:click|prevent=\nconst a = 1;",
+      "/* g:onscroll|passive= */",
+      "const t = `<a on:click|prevent=${1}>`;",
+      "const r = / on:x|y=/;",
+      "const a = <p> on:x|y= </p>;",
+    ] {
+      assert!(
+        matches!(mask(input), Ok(Cow::Borrowed(_))),
+        "Input: {input}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_event_names_with_the_modifier_separator() {
+    for input in ["<a on:my$event={f} />", "<a on:my$event|once={f} />", "<a href=\"x\" on:click|once={f} g:on$x|passive={g} />"] {
+      assert!(
+        matches!(mask(input), Err(ParserError::ParseMsg { msg: "Event names can't contain \"$\"", .. })),
+        "Input: {input}"
+      );
+    }
+  }
 }
@@ -0,0 +1,95 @@
+use crate::error::ParserError;
+use std::{
+  collections::{HashMap, HashSet},
+  fmt::Write,
+};
+
+/// `const var = from.firstChild;` or `.nextSibling`, one step of the walk over a cloned template.
+#[derive(Debug)]
+pub(super) struct Step {
+  var: String,
+  from: String,
+  prop: &'static str,
+  /// Left out of the output when empty, like in compact output.
+  comment: String,
+}
+
+/// Walk of the template cloned by one generated function, and the nodes its setup code uses.
+#[derive(Debug, Default)]
+pub(super) struct Walk {
+  steps: Vec<Step>,
+  used: HashSet<String>,
+}
+
+impl Walk {
+  pub(super) fn step(&mut self, var: &str, from: &str, prop: &'static str, comment: String) {
+    self.steps.push(Step {
+      var: var.to_string(),
+      from: from.to_string(),
+      prop,
+      comment,
+    });
+  }
+
+  /// Marks the node in `var` as used by the setup code, it gets a variable.
+  pub(super) fn use_var(&mut self, var: &str) {
+    self.used.insert(var.to_string());
+  }
+}
+
+/// Property reads on cloned templates, walking to every node and only to the nodes used.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct WalkStats {
+  pub(super) full: usize,
+  pub(super) minimal: usize,
+}
+
+/// Declares the steps of `walk` so only used nodes get a variable. Skipped nodes are folded into the
+/// path of the next one (`el0.firstChild.nextSibling.firstChild`), and ancestors shared by several
+/// used nodes keep theirs so the walk never starts over from the root.
+pub(super) fn minimal_walk(walk: &Walk, stats: &mut WalkStats) -> Result<String, ParserError> {
+  let steps = walk
+    .steps
+    .iter()
+    .map(|step| (step.var.as_str(), step))
+    .collect::<HashMap<_, _>>();
+
+  // Edges of the walk leading to a used node, a node with two of them is an ancestor worth keeping
+  let mut edges = HashSet::new();
+  let mut branches = HashMap::<&str, usize>::new();
+  for var in &walk.used {
+    let mut node = var.as_str();
+    while let Some(step) = steps.get(node) {
+      if !edges.insert((step.from.as_str(), node)) {
+        break;
+      }
+      *branches.entry(step.from.as_str()).or_default() += 1;
+      node = &step.from;
+    }
+  }
+  let kept = |var: &str| walk.used.contains(var) || branches.get(var).is_some_and(|n| *n > 1);
+
+  let mut ret = String::new();
+  for step in &walk.steps {
+    stats.full += 1;
+    if !kept(&step.var) {
+      continue;
+    }
+
+    let mut path = vec![step.prop];
+    let mut from = step.from.as_str();
+    while let Some(prev) = steps.get(from).filter(|_| !kept(from)) {
+      path.push(prev.prop);
+      from = &prev.from;
+    }
+    stats.minimal += path.len();
+    path.reverse();
+    write!(ret, "const {} = {from}.{};", step.var, path.join("."))?;
+    match step.comment.as_str() {
+      "" => writeln!(ret)?,
+      comment => writeln!(ret, " // {comment}")?,
+    }
+  }
+
+  Ok(ret)
+}
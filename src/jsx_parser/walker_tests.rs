@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+  use super::super::{
+    gen_tests::tests::transform,
+    walker::{minimal_walk, Walk, WalkStats},
+    GenOptions, GlobalState, JsParser, Q_JSX_TEMPLATE,
+  };
+
+  /// The walk of `<div><p>a<b>b</b></p><p>{x}{y}c</p></div>`, `used` are the nodes the setup code uses.
+  fn walk(used: &[usize]) -> (String, WalkStats) {
+    let mut walk = Walk::default();
+    for (var, from, prop, comment) in [
+      (1, 0, "firstChild", "jsx_element"),
+      (2, 1, "firstChild", "jsx_text"),
+      (3, 2, "nextSibling", "jsx_element"),
+      (4, 3, "firstChild", "jsx_text"),
+      (5, 1, "nextSibling", "jsx_element"),
+      (6, 5, "firstChild", "jsx_expression"),
+      (7, 6, "nextSibling", "jsx_expression"),
+      (8, 7, "nextSibling", "jsx_text"),
+    ] {
+      walk.step(&format!("_jsx$el{var}"), &format!("_jsx$el{from}"), prop, comment.to_string());
+    }
+    for var in used {
+      walk.use_var(&format!("_jsx$el{var}"));
+    }
+
+    let mut stats = WalkStats::default();
+    let steps = minimal_walk(&walk, &mut stats).expect("Walk should be declared");
+    (steps, stats)
+  }
+
+  #[test]
+  fn test_only_used_nodes_are_walked() {
+    let (steps, stats) = walk(&[7]);
+
+    assert_eq!(steps, "const _jsx$el7 = _jsx$el0.firstChild.nextSibling.firstChild.nextSibling; // jsx_expression\n");
+    assert_eq!((stats.full, stats.minimal), (8, 4));
+  }
+
+  #[test]
+  fn test_shared_ancestors_are_kept() {
+    let (steps, stats) = walk(&[6, 7, 3]);
+
+    assert_eq!(
+      steps,
+      "const _jsx$el1 = _jsx$el0.firstChild; // jsx_element
+const _jsx$el3 = _jsx$el1.firstChild.nextSibling; // jsx_element
+const _jsx$el6 = _jsx$el1.nextSibling.firstChild; // jsx_expression
+const _jsx$el7 = _jsx$el6.nextSibling; // jsx_expression
+"
+    );
+    assert_eq!(stats.minimal, 6);
+  }
+
+  #[test]
+  fn test_nested_functions_keep_their_variables() {
+    // `_jsx$el3` of the nested function is a different node than the one in this template
+    let out = transform(br#"<div><p>a<b>b</b></p><p>{x}{<i>{y}</i>}c</p></div>"#);
+
+    let outer = out.split("(() => {").nth(1).expect("Outer function should be generated");
+    assert!(!outer.contains("const _jsx$el3 ="), "{out}");
+    assert!(out.contains("const _jsx$el1 = _jsx$el0.firstChild; // jsx_expression"), "{out}");
+  }
+
+  /// Compiles the sample app and compares the property reads on cloned templates with and without
+  /// dropping unused nodes, the counts are pinned so changes to the walk show up here.
+  #[test]
+  fn bench_sample_dom_accesses() {
+    let source = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/js/sample/index.tsx")).expect("Sample should exist");
+    let mut parser = JsParser::from_query(Q_JSX_TEMPLATE).expect("JsParser should be created");
+    let mut state = GlobalState::new(None, GenOptions::default());
+    let mut out = Vec::new();
+    parser
+      .parse_jsx_templates(&source, &mut state, &mut out)
+      .expect("Sample should compile");
+
+    let WalkStats { full, minimal } = state.walk_stats;
+    assert!(minimal < full, "{minimal} >= {full}");
+    assert_eq!((full, minimal), (32, 25));
+  }
+}
@@ -3,8 +3,13 @@ mod error;
 mod jsx_parser;
 
 use error::ParserError;
-use jsx_parser::{GlobalState, JsParser, JsxTemplate};
-use std::{env, fs, io::Read, path::PathBuf, time::Instant};
+use jsx_parser::{GenOptions, GlobalState, JsParser, OutputStyle};
+use std::{
+  env, fs,
+  io::Read,
+  path::{Path, PathBuf},
+  time::Instant,
+};
 
 fn main() -> Result<(), ParserError> {
   let args = CliArgs::read()?;
@@ -23,7 +28,14 @@ fn main() -> Result<(), ParserError> {
 
   let mut jsx_parser = JsParser::from_query(jsx_parser::Q_JSX_TEMPLATE)?;
   let mut outbuf = Vec::new();
-  let mut state = GlobalState::new(args.import_path);
+  let mut state = GlobalState::new(
+    args.import_path,
+    GenOptions {
+      custom_element_props: args.custom_element_props,
+      delegate_events: args.delegate_events,
+      output_style: args.output_style,
+    },
+  );
 
   for path in paths {
     let source = if args.comment_directives {
@@ -34,53 +46,13 @@ fn main() -> Result<(), ParserError> {
       &file_buf
     };
 
-    let tree = jsx_parser.tree(source)?;
-    let matches = jsx_parser.parse(tree.root_node(), source)?;
-
-    let templates = matches
-      .enumerate()
-      .map(|(i, m)| JsxTemplate::parse(i, m.captures, source))
-      .collect::<Result<Box<_>, ParserError>>()?;
-
-    let template_parts = templates
-      .iter()
-      .enumerate()
-      .rev()
-      .filter_map(|(i, template)| {
-        (template.is_root
-          && !templates.iter().rev().take(templates.len() - 1 - i).any(|t| {
-            let range = t.start..t.end + 1;
-            range.contains(&template.start) && range.contains(&template.end)
-          }))
-        .then_some(
-          template
-            .parts(&templates, &mut state)
-            .map(|parts| (template, parts)),
-        )
-      })
-      .collect::<Result<Box<_>, _>>()?;
-
-    let mut src_idx = 0;
-    if source.len() > outbuf.capacity() {
-      outbuf.reserve(source.len() - outbuf.capacity());
-    }
-    outbuf.extend_from_slice(state.generate_setup_js(&templates)?.as_bytes());
-
-    for (template, parts) in template_parts.iter().rev() {
-      outbuf.extend_from_slice(&source[src_idx..template.start]);
-      outbuf.extend_from_slice(parts.create_fn.as_bytes());
-      src_idx = template.end;
-    }
-
-    if src_idx < source.len() {
-      outbuf.extend_from_slice(&source[src_idx..]);
+    let relpath = path.strip_prefix(&args.dir).expect("path is not child of input dir");
+    if let Some(module) = &args.shared_templates {
+      state.set_templates_import(Some(shared_templates_import(relpath, module)));
     }
+    jsx_parser.parse_jsx_templates(source, &mut state, &mut outbuf)?;
 
-    let outpath = args.outdir.join(
-      path
-        .strip_prefix(&args.dir)
-        .expect("path is not child of input dir"),
-    );
+    let outpath = args.outdir.join(relpath);
     fs::create_dir_all(outpath.parent().expect("no input dir"))?;
     fs::write(outpath, &outbuf)?;
 
@@ -89,6 +61,14 @@ fn main() -> Result<(), ParserError> {
     parsed_buf.clear();
   }
 
+  if let Some(module) = &args.shared_templates
+    && let Some(contents) = state.generate_shared_templates()?
+  {
+    let outpath = args.outdir.join(module);
+    fs::create_dir_all(outpath.parent().expect("no output dir"))?;
+    fs::write(outpath, contents)?;
+  }
+
   println!(
     "\x1b[38;5;159m\x1b[1m  JSX\x1b[22m compiled in \x1b[1m\x1b[38;5;157m{:?}\x1b[0m",
     t.elapsed()
@@ -97,12 +77,26 @@ fn main() -> Result<(), ParserError> {
   Ok(())
 }
 
+/// Import path of the shared templates module relative to the output of `relpath`.
+fn shared_templates_import(relpath: &Path, module: &str) -> String {
+  let depth = relpath.parent().map_or(0, |p| p.components().count());
+  match depth {
+    0 => format!("./{module}"),
+    _ => format!("{}{module}", "../".repeat(depth)),
+  }
+}
+
 #[derive(Debug)]
 pub struct CliArgs {
   pub dir: PathBuf,
   pub import_path: Option<String>,
   pub outdir: PathBuf,
   pub comment_directives: bool,
+  pub custom_element_props: bool,
+  pub delegate_events: bool,
+  /// Module, relative to the output directory, every file imports its templates from.
+  pub shared_templates: Option<String>,
+  pub output_style: OutputStyle,
 }
 
 impl CliArgs {
@@ -112,6 +106,13 @@ impl CliArgs {
       import_path: Self::find_flag("-import"),
       outdir: PathBuf::from(Self::find_flag("-out").unwrap_or("build".into())),
       comment_directives: env::args().any(|arg| arg == "-comment-directives"),
+      custom_element_props: env::args().any(|arg| arg == "-custom-element-props"),
+      delegate_events: env::args().any(|arg| arg == "-delegate-events"),
+      shared_templates: Self::find_flag("-shared-templates"),
+      output_style: Self::find_flag("-output-style")
+        .map(|style| style.parse())
+        .transpose()?
+        .unwrap_or_default(),
     })
   }
 